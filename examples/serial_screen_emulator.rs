@@ -0,0 +1,277 @@
+// 串口屏幕模拟器: 使用伪终端(PTY)模拟USB串口屏幕，用于没有硬件时测试 UsbScreen::USBSerial
+// 回复 ReadInfo，解析 IMAGE_AA 帧头 + LZ4数据 + IMAGE_BB 帧尾，把还原的画面保存为PNG
+//
+// cargo run --example serial_screen_emulator -- --width 160 --height 128 --output frames
+// 模拟器启动后会打印伪终端的名称，通过环境变量让 USB-Screen 查找这个串口:
+// USB_SCREEN_EXTRA_PORTS=/dev/pts/3 ./USB-Screen run 160x128_10fps.screen
+// 模拟老设备(不回复ReadInfo，通过串号识别，使用分段发送):
+// cargo run --example serial_screen_emulator -- --legacy
+// USB_SCREEN_EXTRA_PORTS=/dev/pts/3=USBSCR160x128 ./USB-Screen run 160x128_10fps.screen
+
+#[cfg(unix)]
+fn main() -> anyhow::Result<()> {
+    emulator::run()
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("串口屏幕模拟器需要伪终端，只支持Linux/macOS");
+}
+
+#[cfg(unix)]
+mod emulator {
+    use std::{
+        io::{Read, Write},
+        path::PathBuf,
+        time::Duration,
+    };
+
+    use anyhow::{anyhow, Result};
+    use clap::Parser;
+    use image::RgbImage;
+    use serialport::{SerialPort, TTYPort};
+
+    const IMAGE_AA: &[u8; 8] = b"image_aa";
+    const IMAGE_BB: &[u8; 8] = b"image_bb";
+    const CMD_BACKLIGHT: &[u8; 8] = b"backlite";
+    const CMD_DISPLAY_POWER: &[u8; 8] = b"displayp";
+    const READ_INFO: &[u8] = b"ReadInfo\n";
+    const WIFI_KEY_MAGIC: &[u8; 8] = b"wflz4ke_";
+    const WIFI_DLT_MAGIC: &[u8; 8] = b"wflz4dl_";
+    const WIFI_NOP_MAGIC: &[u8; 8] = b"wflz4no_";
+
+    #[derive(Parser, Debug)]
+    #[command(about = "串口屏幕模拟器")]
+    struct Args {
+        /// 屏幕宽度
+        #[arg(long, default_value_t = 160)]
+        width: u16,
+        /// 屏幕高度
+        #[arg(long, default_value_t = 128)]
+        height: u16,
+        /// 保存还原的帧(PNG)的目录
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// 收到指定数量的帧后退出，0表示不退出
+        #[arg(long, default_value_t = 0)]
+        frames: u64,
+        /// 在ReadInfo中声明支持差分帧(FEAT:DELTA)
+        #[arg(long)]
+        delta: bool,
+        /// 在ReadInfo中声明支持背光和开关命令(FEAT:BACKLIGHT,POWER)
+        #[arg(long)]
+        control: bool,
+        /// 模拟老设备: 不回复ReadInfo
+        #[arg(long)]
+        legacy: bool,
+    }
+
+    struct Screen {
+        args: Args,
+        // 屏幕上的画面(RGB565 Big-Endian)
+        canvas: Vec<u8>,
+        // 差分帧的参考帧
+        reference: Option<Vec<u8>>,
+        frames: u64,
+    }
+
+    pub fn run() -> Result<()> {
+        let args = Args::parse();
+        if let Some(output) = args.output.as_ref() {
+            std::fs::create_dir_all(output)?;
+        }
+        let (mut master, slave) = TTYPort::pair()?;
+        let name = slave.name().ok_or(anyhow!("无法获取伪终端名称"))?;
+        // 不保持从设备打开，否则 USB-Screen 无法获得串口的独占锁
+        drop(slave);
+        println!("串口屏幕模拟器已启动: {name} 屏幕大小:{}x{}", args.width, args.height);
+        if args.legacy {
+            println!("USB_SCREEN_EXTRA_PORTS={name}=USBSCR{}x{}", args.width, args.height);
+        } else {
+            println!("USB_SCREEN_EXTRA_PORTS={name}");
+        }
+        master.set_timeout(Duration::from_millis(100))?;
+
+        let canvas = vec![0u8; args.width as usize * args.height as usize * 2];
+        let mut screen = Screen { args, canvas, reference: None, frames: 0 };
+        let mut buf: Vec<u8> = vec![];
+        let mut tmp = [0u8; 64 * 1024];
+        loop {
+            match master.read(&mut tmp) {
+                Ok(n) if n > 0 => buf.extend_from_slice(&tmp[..n]),
+                Ok(_) => {}
+                Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(_) => {
+                    // 从设备没有被打开(USB-Screen 关闭了串口)
+                    // 伪终端在主设备关闭前不会释放，USB-Screen 打开串口时设置的独占标志(TIOCEXCL)不会清除，
+                    // 这里打开一次并取消独占，USB-Screen 才能再次打开(探测之后还要重新打开)
+                    if let Ok(mut port) = serialport::new(&name, 115_200).open_native() {
+                        let _ = port.set_exclusive(false);
+                    }
+                    buf.clear();
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            }
+            while screen.process(&mut buf, &mut master)? {}
+            if screen.args.frames > 0 && screen.frames >= screen.args.frames {
+                println!("已收到{}帧，退出", screen.frames);
+                return Ok(());
+            }
+        }
+    }
+
+    fn find(data: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+        if data.len() < pattern.len() {
+            return None;
+        }
+        (from..=data.len() - pattern.len()).find(|i| &data[*i..*i + pattern.len()] == pattern)
+    }
+
+    impl Screen {
+        // 从缓冲区中处理一条完整的命令，数据不完整时返回false等待更多数据
+        fn process(&mut self, buf: &mut Vec<u8>, port: &mut TTYPort) -> Result<bool> {
+            // 丢弃不认识的数据，直到下一个命令
+            let starts = [find(buf, READ_INFO, 0), find(buf, IMAGE_AA, 0), find(buf, CMD_BACKLIGHT, 0), find(buf, CMD_DISPLAY_POWER, 0)];
+            let start = match starts.iter().flatten().min() {
+                Some(start) => *start,
+                None => {
+                    // 保留末尾可能是半个命令的数据
+                    let keep = buf.len().min(8);
+                    buf.drain(..buf.len() - keep);
+                    return Ok(false);
+                }
+            };
+            if start > 0 {
+                println!("丢弃{start}字节未知数据");
+                buf.drain(..start);
+            }
+
+            if buf.starts_with(READ_INFO) {
+                buf.drain(..READ_INFO.len());
+                if self.args.legacy {
+                    println!("ReadInfo (老设备，不回复)");
+                } else {
+                    let mut info = format!("ESP32-WIFI-SCREEN;{};{};PROTO:USB-SCREEN", self.args.width, self.args.height);
+                    let mut features = vec![];
+                    if self.args.delta {
+                        features.push("DELTA");
+                    }
+                    if self.args.control {
+                        features.extend(["BACKLIGHT", "POWER"]);
+                    }
+                    if !features.is_empty() {
+                        info.push_str(&format!(";FEAT:{}", features.join(",")));
+                    }
+                    println!("ReadInfo -> {info}");
+                    port.write_all(format!("{info}\n").as_bytes())?;
+                    port.flush()?;
+                }
+                return Ok(true);
+            }
+
+            if buf.starts_with(CMD_BACKLIGHT) || buf.starts_with(CMD_DISPLAY_POWER) {
+                if buf.len() < 10 {
+                    return Ok(false);
+                }
+                let value = u16::from_be_bytes([buf[8], buf[9]]);
+                if buf.starts_with(CMD_BACKLIGHT) {
+                    println!("背光亮度: {value}");
+                } else {
+                    println!("屏幕开关: {}", if value != 0 { "打开" } else { "关闭" });
+                }
+                buf.drain(..10);
+                return Ok(true);
+            }
+
+            // IMAGE_AA + WIDTH + HEIGHT + X + Y + 数据 + IMAGE_BB
+            if buf.len() < 16 {
+                return Ok(false);
+            }
+            let be = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+            let (width, height, x, y) = (be(8), be(10), be(12), be(14));
+            // 压缩数据中也可能出现帧尾，解码失败时继续查找下一个帧尾
+            let mut from = 16;
+            while let Some(end) = find(buf, IMAGE_BB, from) {
+                let body = buf[16..end].to_vec();
+                match self.decode(&body, width, height) {
+                    Ok((frame_type, rgb565)) => {
+                        buf.drain(..end + IMAGE_BB.len());
+                        self.draw(&rgb565, x, y, width, height)?;
+                        println!("[{}] {frame_type} ({x},{y}) {width}x{height} {}字节", self.frames, body.len());
+                        return Ok(true);
+                    }
+                    Err(_) => from = end + 1,
+                }
+            }
+            Ok(false)
+        }
+
+        fn decode(&mut self, body: &[u8], width: u16, height: u16) -> Result<(&'static str, Vec<u8>)> {
+            let size = width as usize * height as usize * 2;
+            if body.len() >= 12 && [WIFI_KEY_MAGIC, WIFI_DLT_MAGIC, WIFI_NOP_MAGIC].iter().any(|m| body.starts_with(*m)) {
+                // 差分帧固件: 数据是WiFi协议的帧
+                let magic = &body[0..8];
+                if magic == WIFI_NOP_MAGIC {
+                    let reference = self.reference.clone().ok_or(anyhow!("没有参考帧"))?;
+                    return Ok(("NOP", reference));
+                }
+                let data = lz4_flex::decompress_size_prepended(&body[12..])?;
+                if data.len() != size {
+                    return Err(anyhow!("数据大小错误"));
+                }
+                if magic == WIFI_KEY_MAGIC {
+                    self.reference = Some(data.clone());
+                    return Ok(("KEY", data));
+                }
+                let reference = self.reference.as_mut().ok_or(anyhow!("没有参考帧"))?;
+                if reference.len() != size {
+                    return Err(anyhow!("参考帧大小错误"));
+                }
+                for (p, d) in reference.iter_mut().zip(data.iter()) {
+                    *p ^= d;
+                }
+                return Ok(("DLT", reference.clone()));
+            }
+            let data = lz4_flex::decompress_size_prepended(body)?;
+            if data.len() != size {
+                return Err(anyhow!("数据大小错误"));
+            }
+            Ok(("LZ4", data))
+        }
+
+        // 把收到的区域绘制到屏幕上并保存
+        fn draw(&mut self, rgb565: &[u8], x: u16, y: u16, width: u16, height: u16) -> Result<()> {
+            let screen_width = self.args.width as usize;
+            if x as usize + width as usize > screen_width || y + height > self.args.height {
+                eprintln!("图像超出屏幕范围: ({x},{y}) {width}x{height}");
+                return Ok(());
+            }
+            let row_bytes = width as usize * 2;
+            for row in 0..height as usize {
+                let dst = ((y as usize + row) * screen_width + x as usize) * 2;
+                self.canvas[dst..dst + row_bytes].copy_from_slice(&rgb565[row * row_bytes..(row + 1) * row_bytes]);
+            }
+            self.frames += 1;
+            if let Some(output) = self.args.output.as_ref() {
+                let img = rgb565_be_to_rgb888(&self.canvas, self.args.width as u32, self.args.height as u32)
+                    .ok_or(anyhow!("图像转换失败"))?;
+                img.save(output.join(format!("frame_{:05}.png", self.frames)))?;
+            }
+            Ok(())
+        }
+    }
+
+    fn rgb565_be_to_rgb888(rgb565: &[u8], width: u32, height: u32) -> Option<RgbImage> {
+        let mut rgb888 = Vec::with_capacity(rgb565.len() / 2 * 3);
+        for chunk in rgb565.chunks_exact(2) {
+            let pixel = u16::from_be_bytes([chunk[0], chunk[1]]);
+            let r5 = ((pixel >> 11) & 0x1F) as u8;
+            let g6 = ((pixel >> 5) & 0x3F) as u8;
+            let b5 = (pixel & 0x1F) as u8;
+            rgb888.push((r5 << 3) | (r5 >> 2));
+            rgb888.push((g6 << 2) | (g6 >> 4));
+            rgb888.push((b5 << 3) | (b5 >> 2));
+        }
+        RgbImage::from_raw(width, height, rgb888)
+    }
+}
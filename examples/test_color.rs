@@ -97,40 +97,6 @@ fn analyze_color_difference(original: &[u8], converted: &[u8], width: usize, hei
     println!("如果实际最大差异超过此范围，说明可能存在编码/解码问题");
 }
 
-/// 将RGB565大端序字节数组转换回RGB888
-/// 输入: RGB565 大端序字节数组 (每像素2字节)
-/// 输出: RGB888 字节数组 (每像素3字节)
-pub fn rgb565_be_to_rgb888(rgb565: &[u8], width: usize, height: usize) -> Vec<u8> {
-    let pixel_count = width * height;
-    let mut rgb888 = Vec::with_capacity(pixel_count * 3);
-    
-    for chunk in rgb565.chunks_exact(2) {
-        // 从大端序字节还原u16
-        let pixel = u16::from_be_bytes([chunk[0], chunk[1]]);
-        
-        // 从RGB565提取各通道 (格式: RRRRR GGGGGG BBBBB)
-        // R: bits 15-11, G: bits 10-5, B: bits 4-0
-        let r5 = ((pixel >> 11) & 0x1F) as u8;  // 5位红色
-        let g6 = ((pixel >> 5) & 0x3F) as u8;   // 6位绿色
-        let b5 = (pixel & 0x1F) as u8;          // 5位蓝色
-        
-        // 扩展到8位 (通过左移并填充低位来还原精度)
-        // R: 5位 -> 8位: 左移3位，低3位用高3位填充
-        // G: 6位 -> 8位: 左移2位，低2位用高2位填充
-        // B: 5位 -> 8位: 左移3位，低3位用高3位填充
-        let r8 = (r5 << 3) | (r5 >> 2);
-        let g8 = (g6 << 2) | (g6 >> 4);
-        let b8 = (b5 << 3) | (b5 >> 2);
-        
-        rgb888.push(r8);
-        rgb888.push(g8);
-        rgb888.push(b8);
-    }
-    
-    rgb888
-}
-
-
 // 模拟USB串口发送RGB565图像数据，并返回完整帧数据
 pub fn draw_rgb565_serial(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16) -> Result<(Vec<u8>, usize, usize)>{
     
@@ -0,0 +1,440 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, UdpSocket},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use image::RgbImage;
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    ServerConfig, ServerConnection, StreamOwned,
+};
+use tungstenite::Message;
+
+// WiFi屏幕模拟器: 在本机模拟ESP32 WiFi屏幕，用于没有硬件时测试 wifi_screen.rs
+// 提供 /display_config 和 /ws，按照固件的方式解码 wflz4ke_/wflz4dl_/wflz4no_ 帧并回复ACK/NACK
+// --codecs TILE,JPEG 声明支持分块差分帧(wflz4tl_)和JPEG关键帧(wfjpgke_)
+// 还原的帧保存为PNG，可以注入错误测试差分编码、NACK恢复和重新连接
+//
+// cargo run --example wifi_screen_emulator -- --port 8080 --output frames
+// ./USB-Screen run 320x240.screen --device-ip 127.0.0.1:8080
+// 模拟器同时回复局域网发现请求，也可以通过设备ID连接:
+// ./USB-Screen run 320x240.screen --device-id EMULATOR0001
+// 测试加密连接和令牌(证书可以用 openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:P-256 -nodes -subj /CN=screen -keyout key.pem -out cert.pem 生成):
+// cargo run --example wifi_screen_emulator -- --port 8443 --token secret --tls-cert cert.pem --tls-key key.pem
+// ./USB-Screen run 320x240.screen --device-ip 127.0.0.1:8443 --wifi-tls --wifi-token secret --wifi-cert-sha256 <启动时打印的指纹>
+
+const WIFI_KEY_MAGIC: &[u8; 8] = b"wflz4ke_";
+const WIFI_DLT_MAGIC: &[u8; 8] = b"wflz4dl_";
+const WIFI_NOP_MAGIC: &[u8; 8] = b"wflz4no_";
+const WIFI_TILE_MAGIC: &[u8; 8] = b"wflz4tl_";
+const WIFI_JPEG_MAGIC: &[u8; 8] = b"wfjpgke_";
+const DISCOVERY_PORT: u16 = 37020;
+const DISCOVERY_REQUEST: &str = "USB-SCREEN-DISCOVER";
+
+#[derive(Parser, Debug)]
+#[command(about = "WiFi屏幕模拟器")]
+struct Args {
+    /// 监听地址
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+    /// 监听端口
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+    /// 屏幕宽度(旋转后)
+    #[arg(long, default_value_t = 320)]
+    width: u32,
+    /// 屏幕高度(旋转后)
+    #[arg(long, default_value_t = 240)]
+    height: u32,
+    /// 保存还原的帧(PNG)的目录
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// 收到指定数量的帧后退出，0表示不退出
+    #[arg(long, default_value_t = 0)]
+    frames: u64,
+    /// 每N帧回复一次NACK并丢弃参考帧
+    #[arg(long, default_value_t = 0)]
+    nack_every: u64,
+    /// 每N帧不回复ACK(客户端等待超时)
+    #[arg(long, default_value_t = 0)]
+    silent_every: u64,
+    /// 每N帧断开一次连接(测试重新连接)
+    #[arg(long, default_value_t = 0)]
+    drop_every: u64,
+    /// 回复局域网发现请求时使用的设备ID
+    #[arg(long, default_value = "EMULATOR0001")]
+    device_id: String,
+    /// 预共享令牌，设置后拒绝 Authorization 头不正确的请求(HTTP 401)
+    #[arg(long)]
+    token: Option<String>,
+    /// TLS证书(PEM)，和 --tls-key 一起设置后使用 https:// 和 wss://
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// TLS私钥(PEM)
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// 在 /display_config 中声明支持的额外编码(TILE、JPEG)，例如 --codecs TILE,JPEG
+    #[arg(long, value_delimiter = ',')]
+    codecs: Vec<String>,
+}
+
+fn every(n: u64, index: u64) -> bool {
+    n > 0 && index.is_multiple_of(n)
+}
+
+// 固件侧的解码状态，每个连接单独保存参考帧
+struct Decoder {
+    width: u32,
+    height: u32,
+    reference: Option<Vec<u8>>,
+}
+
+impl Decoder {
+    // 解码一帧，返回帧类型和还原的RGB565数据; 出错时需要回复NACK
+    fn decode(&mut self, data: &[u8]) -> Result<(&'static str, Vec<u8>)> {
+        if data.len() < 12 {
+            return Err(anyhow!("帧太短: {}字节", data.len()));
+        }
+        let width = u16::from_be_bytes([data[8], data[9]]) as u32;
+        let height = u16::from_be_bytes([data[10], data[11]]) as u32;
+        if width != self.width || height != self.height {
+            return Err(anyhow!("帧尺寸 {width}x{height} 和屏幕尺寸 {}x{} 不一致", self.width, self.height));
+        }
+        let size = (width * height * 2) as usize;
+        let magic = &data[0..8];
+        if magic == WIFI_KEY_MAGIC {
+            let frame = lz4_flex::decompress_size_prepended(&data[12..])?;
+            if frame.len() != size {
+                return Err(anyhow!("关键帧大小错误: {}", frame.len()));
+            }
+            self.reference = Some(frame.clone());
+            Ok(("KEY", frame))
+        } else if magic == WIFI_DLT_MAGIC {
+            let reference = self.reference.as_mut().ok_or(anyhow!("没有参考帧，无法解码差分帧"))?;
+            let delta = lz4_flex::decompress_size_prepended(&data[12..])?;
+            if delta.len() != size {
+                return Err(anyhow!("差分帧大小错误: {}", delta.len()));
+            }
+            for (p, d) in reference.iter_mut().zip(delta.iter()) {
+                *p ^= d;
+            }
+            Ok(("DLT", reference.clone()))
+        } else if magic == WIFI_TILE_MAGIC {
+            // TILE_SIZE + COUNT + COUNT个分块位置 + 各分块的像素，直接覆盖参考帧
+            let reference = self.reference.as_mut().ok_or(anyhow!("没有参考帧，无法解码分块差分帧"))?;
+            let tiles = lz4_flex::decompress_size_prepended(&data[12..])?;
+            if tiles.len() < 4 {
+                return Err(anyhow!("分块差分帧太短"));
+            }
+            let be = |i: usize| u16::from_be_bytes([tiles[i], tiles[i + 1]]) as usize;
+            let (tile, count) = (be(0), be(2));
+            if tile == 0 || tiles.len() < 4 + count * 4 {
+                return Err(anyhow!("分块差分帧格式错误"));
+            }
+            let (w, h) = (width as usize, height as usize);
+            let mut pixels = 4 + count * 4;
+            for i in 0..count {
+                let (x, y) = (be(4 + i * 4), be(6 + i * 4));
+                if x >= w || y >= h {
+                    return Err(anyhow!("分块位置({x},{y})超出屏幕范围"));
+                }
+                let tile_width = tile.min(w - x);
+                for row in y..h.min(y + tile) {
+                    let dst = (row * w + x) * 2;
+                    let src = tiles.get(pixels..pixels + tile_width * 2).ok_or(anyhow!("分块数据不完整"))?;
+                    reference[dst..dst + tile_width * 2].copy_from_slice(src);
+                    pixels += tile_width * 2;
+                }
+            }
+            Ok(("TILE", reference.clone()))
+        } else if magic == WIFI_JPEG_MAGIC {
+            let img = image::load_from_memory(&data[12..])?.to_rgb8();
+            if img.dimensions() != (width, height) {
+                return Err(anyhow!("JPEG尺寸错误: {:?}", img.dimensions()));
+            }
+            let frame = rgb888_to_rgb565_be(&img);
+            self.reference = Some(frame.clone());
+            Ok(("JPEG", frame))
+        } else if magic == WIFI_NOP_MAGIC {
+            let reference = self.reference.as_ref().ok_or(anyhow!("没有参考帧，无法处理无变化帧"))?;
+            Ok(("NOP", reference.clone()))
+        } else {
+            Err(anyhow!("未知的帧类型: {:?}", String::from_utf8_lossy(magic)))
+        }
+    }
+}
+
+fn rgb888_to_rgb565_be(img: &RgbImage) -> Vec<u8> {
+    let mut rgb565 = Vec::with_capacity(img.len() / 3 * 2);
+    for p in img.pixels() {
+        let [r, g, b] = p.0;
+        let pixel = ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3);
+        rgb565.extend_from_slice(&pixel.to_be_bytes());
+    }
+    rgb565
+}
+
+fn rgb565_be_to_rgb888(rgb565: &[u8], width: u32, height: u32) -> Option<RgbImage> {
+    let mut rgb888 = Vec::with_capacity(rgb565.len() / 2 * 3);
+    for chunk in rgb565.chunks_exact(2) {
+        let pixel = u16::from_be_bytes([chunk[0], chunk[1]]);
+        let r5 = ((pixel >> 11) & 0x1F) as u8;
+        let g6 = ((pixel >> 5) & 0x3F) as u8;
+        let b5 = (pixel & 0x1F) as u8;
+        rgb888.push((r5 << 3) | (r5 >> 2));
+        rgb888.push((g6 << 2) | (g6 >> 4));
+        rgb888.push((b5 << 3) | (b5 >> 2));
+    }
+    RgbImage::from_raw(width, height, rgb888)
+}
+
+fn main() -> Result<()> {
+    let args = Arc::new(Args::parse());
+    if let Some(output) = args.output.as_ref() {
+        std::fs::create_dir_all(output)?;
+    }
+    let tls = match (args.tls_cert.as_ref(), args.tls_key.as_ref()) {
+        (Some(cert), Some(key)) => Some(load_tls_config(cert, key)?),
+        _ => None,
+    };
+    let listener = TcpListener::bind((args.host.as_str(), args.port))?;
+    println!("WiFi屏幕模拟器已启动: {}:{} 屏幕大小:{}x{}", args.host, args.port, args.width, args.height);
+    let frame_index = Arc::new(AtomicU64::new(0));
+    {
+        let args = args.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = handle_discovery(&args) {
+                eprintln!("局域网发现失败:{err:?}");
+            }
+        });
+    }
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(err) => {
+                eprintln!("接受连接失败:{err:?}");
+                continue;
+            }
+        };
+        let args = args.clone();
+        let frame_index = frame_index.clone();
+        let tls = tls.clone();
+        std::thread::spawn(move || {
+            let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+            let ret = match tls {
+                Some(config) => ServerConnection::new(config)
+                    .map_err(|err| anyhow!("TLS错误:{err}"))
+                    .and_then(|conn| handle_connection(StreamOwned::new(conn, stream), &peer, &args, &frame_index)),
+                None => handle_connection(stream, &peer, &args, &frame_index),
+            };
+            if let Err(err) = ret {
+                eprintln!("连接错误 {peer}:{err:?}");
+            }
+        });
+    }
+    Ok(())
+}
+
+// 回复局域网发现请求: ESP32-WIFI-SCREEN;ID:{设备ID};SIZE:{宽}x{高};PORT:{端口}
+fn handle_discovery(args: &Args) -> Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT))?;
+    let mut buf = [0u8; 512];
+    loop {
+        let (n, addr) = socket.recv_from(&mut buf)?;
+        if String::from_utf8_lossy(&buf[..n]).trim() != DISCOVERY_REQUEST {
+            continue;
+        }
+        let reply = format!("ESP32-WIFI-SCREEN;ID:{};SIZE:{}x{};PORT:{}", args.device_id, args.width, args.height, args.port);
+        println!("局域网发现 {addr} -> {reply}");
+        socket.send_to(reply.as_bytes(), addr)?;
+    }
+}
+
+fn load_tls_config(cert: &PathBuf, key: &PathBuf) -> Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key)?;
+    let first = certs.first().ok_or(anyhow!("证书文件中没有证书"))?;
+    let fingerprint = aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA256, first.as_ref());
+    let fingerprint: String = fingerprint.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+    println!("TLS证书SHA-256指纹: {fingerprint}");
+    let config = ServerConfig::builder().with_no_client_auth().with_single_cert(certs, key)?;
+    Ok(Arc::new(config))
+}
+
+// 读出的请求头再交给 HTTP 或 websocket 处理
+struct Replay<S> {
+    head: Vec<u8>,
+    pos: usize,
+    inner: S,
+}
+
+impl<S: Read> Read for Replay<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos < self.head.len() {
+            let n = buf.len().min(self.head.len() - self.pos);
+            buf[..n].copy_from_slice(&self.head[self.pos..self.pos + n]);
+            self.pos += n;
+            return Ok(n);
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for Replay<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 和固件一样在同一个端口上处理HTTP请求和websocket
+fn handle_connection<S: Read + Write>(mut stream: S, peer: &str, args: &Args, frame_index: &AtomicU64) -> Result<()> {
+    // 先读取请求头，websocket握手交给tungstenite处理
+    let mut head = vec![];
+    let mut buf = [0u8; 2048];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < 8192 {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let text = String::from_utf8_lossy(&head).to_string();
+    let path = text.split_whitespace().nth(1).unwrap_or("/").to_string();
+    if !authorized(&text, args) {
+        println!("HTTP {path} -> 401 令牌错误");
+        let body = "Unauthorized";
+        write!(
+            stream,
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()?;
+        return Ok(());
+    }
+    let stream = Replay { head, pos: 0, inner: stream };
+    if path == "/ws" {
+        handle_websocket(stream, peer, args, frame_index)
+    } else {
+        handle_http(stream, &path, args)
+    }
+}
+
+// 检查请求头中的 Authorization: Bearer {令牌}
+fn authorized(head: &str, args: &Args) -> bool {
+    let token = match args.token.as_ref() {
+        Some(token) => token,
+        None => return true,
+    };
+    head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| name.trim().eq_ignore_ascii_case("authorization") && value.trim() == format!("Bearer {token}"))
+}
+
+fn handle_http<S: Write>(mut stream: S, path: &str, args: &Args) -> Result<()> {
+    // 请求头已经读取，不需要读取请求内容
+    let (status, body) = match path {
+        "/display_config" => (
+            "200 OK",
+            serde_json::json!({
+                "display_type": "EMULATOR",
+                "rotated_width": args.width,
+                "rotated_height": args.height,
+                "codecs": args.codecs,
+            })
+            .to_string(),
+        ),
+        "/draw_canvas" => ("200 OK", "OK".to_string()),
+        _ => ("404 Not Found", "Not Found".to_string()),
+    };
+    println!("HTTP {path} -> {status}");
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn handle_websocket<S: Read + Write>(stream: S, peer: &str, args: &Args, frame_index: &AtomicU64) -> Result<()> {
+    // 令牌已经在读取请求头时检查过
+    let mut socket = tungstenite::accept(stream).map_err(|err| anyhow!("websocket握手失败:{err}"))?;
+    println!("websocket已连接: {peer}");
+    let mut decoder = Decoder { width: args.width, height: args.height, reference: None };
+    loop {
+        let msg = match socket.read() {
+            Ok(msg) => msg,
+            Err(err) => {
+                println!("websocket已断开: {peer} {err}");
+                return Ok(());
+            }
+        };
+        let data = match msg {
+            Message::Binary(data) => data,
+            Message::Text(text) => {
+                // 控制命令: BRIGHTNESS:50、DISPLAY:ON/OFF
+                println!("控制命令: {text}");
+                continue;
+            }
+            Message::Close(_) => {
+                println!("websocket已关闭: {peer}");
+                return Ok(());
+            }
+            _ => continue,
+        };
+        let index = frame_index.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if every(args.drop_every, index) {
+            println!("[{index}] 注入错误: 断开连接");
+            // 返回后关闭连接
+            return Ok(());
+        }
+        if every(args.nack_every, index) {
+            println!("[{index}] 注入错误: NACK");
+            decoder.reference = None;
+            socket.send(Message::Text("NACK".into()))?;
+            continue;
+        }
+
+        match decoder.decode(&data) {
+            Ok((frame_type, rgb565)) => {
+                println!("[{index}] {frame_type} {}字节", data.len());
+                if let Some(output) = args.output.as_ref() {
+                    match rgb565_be_to_rgb888(&rgb565, args.width, args.height) {
+                        Some(img) => img.save(output.join(format!("frame_{index:05}.png")))?,
+                        None => eprintln!("[{index}] 图像转换失败"),
+                    }
+                }
+                if every(args.silent_every, index) {
+                    println!("[{index}] 注入错误: 不回复ACK");
+                } else {
+                    socket.send(Message::Text("ACK".into()))?;
+                }
+            }
+            Err(err) => {
+                // 固件解码失败后丢弃参考帧，等待关键帧
+                println!("[{index}] 解码失败: {err}，回复NACK");
+                decoder.reference = None;
+                socket.send(Message::Text("NACK".into()))?;
+            }
+        }
+
+        if args.frames > 0 && index >= args.frames {
+            println!("已收到{index}帧，退出");
+            let _ = socket.close(None);
+            let _ = socket.flush();
+            std::process::exit(0);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use clap::{Args, Parser, Subcommand};
+
+use crate::{headless::RenderOptions, schedule::ScreenSchedule, screen::ScreenRender, wifi_screen::{ScaleMode, Scaling, WifiCodec}, wifi_security::WifiSecurity};
+
+#[derive(Parser)]
+#[command(name = "USB-Screen", version, about = "USB屏幕&编辑器")]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+    /// 兼容旧用法: 直接传入 .screen 文件，等同于 run <FILE>
+    pub file: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// 打开 .screen 文件并持续刷新到屏幕
+    Run {
+        /// .screen 文件路径，不指定时在当前目录查找; 传入多个文件时同时驱动多个屏幕，
+        /// 每个文件需要在文件中设置各自的设备地址或IP
+        files: Vec<String>,
+        #[command(flatten)]
+        overrides: ScreenOverrides,
+        /// 启动本地HTTP控制接口，例如 127.0.0.1:8080
+        #[arg(long, value_name = "ADDR")]
+        http: Option<String>,
+    },
+    /// 列出所有可用的 USB / 串口屏幕设备
+    ListDevices,
+    /// 无头渲染: 把帧写入 PNG 序列或 GIF 动画，不连接设备
+    Render(RenderOptions),
+    /// 打印 .screen 文件的参数和组件列表
+    Inspect {
+        file: String,
+        /// 以 JSON 格式输出
+        #[arg(long)]
+        json: bool,
+    },
+    /// 打开编辑器
+    Editor,
+}
+
+/// 覆盖 .screen 文件中保存的参数
+#[derive(Args, Clone, Debug, Default)]
+pub struct ScreenOverrides {
+    /// 刷新帧率
+    #[arg(long, value_parser = parse_fps)]
+    pub fps: Option<f32>,
+    /// 旋转角度: 0, 90, 180, 270
+    #[arg(long, value_parser = parse_rotate_degree)]
+    pub rotate_degree: Option<i32>,
+    /// 设备地址: USB Raw 设备地址或者串口名称，传入空字符串时清除
+    #[arg(long)]
+    pub device_address: Option<String>,
+    /// WiFi屏幕IP地址，传入空字符串时清除(改用USB屏幕)
+    #[arg(long)]
+    pub device_ip: Option<String>,
+    /// WiFi屏幕设备ID，通过局域网发现查找屏幕的IP地址(见 list-devices)，传入空字符串时清除
+    #[arg(long)]
+    pub device_id: Option<String>,
+    /// 屏幕背光亮度: 0~100
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub brightness: Option<u8>,
+    /// 安静时段，期间关闭屏幕，例如 23:00-07:00
+    #[arg(long, value_parser = ScreenSchedule::parse_quiet_hours)]
+    pub quiet_hours: Option<(String, String)>,
+    /// 主机空闲(CPU占用率低于5%)多少分钟后关闭屏幕
+    #[arg(long)]
+    pub idle_minutes: Option<u32>,
+    /// WiFi屏幕分辨率和画面宽高比不一致时的缩放方式: stretch(拉伸)、fit(完整显示)、fill(裁剪填满)、integer(整数倍)
+    #[arg(long, value_parser = ScaleMode::parse)]
+    pub scale_mode: Option<ScaleMode>,
+    /// fit、integer 缩放时空白部分的颜色，例如 #000000
+    #[arg(long, value_parser = Scaling::parse_color)]
+    pub letterbox_color: Option<[u8; 3]>,
+    /// WiFi屏幕的编码: auto(固件支持时使用JPEG关键帧)、lossless(只使用无损编码)
+    #[arg(long, value_parser = WifiCodec::parse)]
+    pub wifi_codec: Option<WifiCodec>,
+    /// WiFi屏幕使用加密连接(wss:// 和 https://)
+    #[arg(long)]
+    pub wifi_tls: bool,
+    /// WiFi屏幕的预共享令牌，传入空字符串时清除
+    #[arg(long)]
+    pub wifi_token: Option<String>,
+    /// WiFi屏幕证书的SHA-256指纹，固件使用自签名证书时设置，传入空字符串时清除
+    #[arg(long, value_parser = WifiSecurity::parse_fingerprint)]
+    pub wifi_cert_sha256: Option<String>,
+}
+
+impl ScreenOverrides {
+    pub fn apply(&self, render: &mut ScreenRender) {
+        if let Some(fps) = self.fps {
+            render.fps = fps;
+        }
+        if let Some(rotate_degree) = self.rotate_degree {
+            render.rotate_degree = rotate_degree;
+        }
+        if let Some(device_address) = self.device_address.as_ref() {
+            render.device_address = non_empty(device_address);
+        }
+        if let Some(device_ip) = self.device_ip.as_ref() {
+            render.device_ip = non_empty(device_ip);
+        }
+        if let Some(device_id) = self.device_id.as_ref() {
+            render.device_id = non_empty(device_id);
+        }
+        if let Some(brightness) = self.brightness {
+            render.brightness = Some(brightness);
+        }
+        if let Some((start, end)) = self.quiet_hours.as_ref() {
+            let schedule = render.schedule.get_or_insert_with(Default::default);
+            schedule.quiet_start = Some(start.clone());
+            schedule.quiet_end = Some(end.clone());
+        }
+        if let Some(idle_minutes) = self.idle_minutes {
+            render.schedule.get_or_insert_with(Default::default).idle_minutes = Some(idle_minutes);
+        }
+        if let Some(mode) = self.scale_mode {
+            render.scaling.mode = mode;
+        }
+        if let Some(color) = self.letterbox_color {
+            render.scaling.background = color;
+        }
+        if let Some(codec) = self.wifi_codec {
+            render.wifi_codec = codec;
+        }
+        if self.wifi_tls {
+            render.wifi_security.tls = true;
+        }
+        if let Some(token) = self.wifi_token.as_ref() {
+            render.wifi_security.token = non_empty(token);
+        }
+        if let Some(fingerprint) = self.wifi_cert_sha256.as_ref() {
+            render.wifi_security.cert_sha256 = non_empty(fingerprint);
+        }
+    }
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.trim().is_empty() {
+        None
+    } else {
+        Some(s.trim().to_string())
+    }
+}
+
+fn parse_fps(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(fps) if fps > 0. => Ok(fps),
+        _ => Err(format!("无效的帧率: {s}")),
+    }
+}
+
+fn parse_rotate_degree(s: &str) -> Result<i32, String> {
+    match s.parse::<i32>() {
+        Ok(v @ (0 | 90 | 180 | 270)) => Ok(v),
+        _ => Err(format!("旋转角度只能是 0, 90, 180, 270: {s}")),
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    fmt,
+    io::{Cursor, Read},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use image::{ImageFormat, RgbImage};
+use log::{error, info};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    screen::ScreenRender,
+    widgets::{ImageData, ImageWidget, TextWidget},
+};
+
+// 本地HTTP控制接口: 在不修改screen文件的情况下修改正在运行的屏幕
+// 请求在HTTP线程中解析，然后通过通道发送到屏幕的主循环中执行
+
+pub enum Command {
+    ListWidgets,
+    SetText { id: String, text: String },
+    SetImage { id: String, data: Vec<u8> },
+    SetFps(f32),
+    SetBrightness(u8),
+    SetDisplayOn(bool),
+}
+
+type Reply = Result<Value>;
+
+// 请求体的最大大小(图像文件)
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+// 请求和组件的当前状态冲突，返回409
+#[derive(Debug)]
+struct Conflict(String);
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+// 屏幕主循环持有的一端
+pub struct ScreenControl {
+    receiver: Receiver<(Command, Sender<Reply>)>,
+    last_frame: Arc<Mutex<Option<RgbImage>>>,
+}
+
+// HTTP线程持有的一端
+struct ScreenHandle {
+    file: String,
+    sender: Sender<(Command, Sender<Reply>)>,
+    last_frame: Arc<Mutex<Option<RgbImage>>>,
+}
+
+impl ScreenControl {
+    // 在主循环中调用，处理所有等待中的请求
+    pub fn process(&self, render: &mut ScreenRender) {
+        while let Ok((command, reply)) = self.receiver.try_recv() {
+            let _ = reply.send(execute(render, command));
+        }
+    }
+
+    pub fn set_last_frame(&self, frame: &RgbImage) {
+        if let Ok(mut last_frame) = self.last_frame.lock() {
+            *last_frame = Some(frame.clone());
+        }
+    }
+}
+
+// 启动HTTP服务，为每个screen文件返回一个 ScreenControl
+pub fn start(addr: &str, files: &[String]) -> Result<Vec<ScreenControl>> {
+    let server = Server::http(addr).map_err(|err| anyhow!("控制接口启动失败 {addr}: {err}"))?;
+    let mut controls = vec![];
+    let mut handles = vec![];
+    for file in files {
+        let (sender, receiver) = bounded(10);
+        let last_frame = Arc::new(Mutex::new(None));
+        controls.push(ScreenControl { receiver, last_frame: last_frame.clone() });
+        handles.push(ScreenHandle { file: file.clone(), sender, last_frame });
+    }
+    eprintln!("控制接口已启动: http://{addr}");
+    info!("控制接口已启动:{addr}");
+    std::thread::Builder::new()
+        .name("control-api".to_string())
+        .spawn(move || {
+            for request in server.incoming_requests() {
+                handle_request(&handles, request);
+            }
+        })?;
+    Ok(controls)
+}
+
+fn execute(render: &mut ScreenRender, command: Command) -> Reply {
+    match command {
+        Command::ListWidgets => {
+            let widgets: Vec<Value> = render
+                .widgets
+                .iter_mut()
+                .map(|w| {
+                    let p = w.position();
+                    let mut info = json!({
+                        "id": w.id(),
+                        "type_name": w.type_name(),
+                        "label": w.get_label(),
+                        "position": [p.left, p.top, p.right, p.bottom],
+                    });
+                    if let Some(text) = w.as_any_mut().downcast_mut::<TextWidget>() {
+                        info["text"] = json!(text.text);
+                    }
+                    info
+                })
+                .collect();
+            Ok(json!({
+                "width": render.width,
+                "height": render.height,
+                "fps": render.fps,
+                "brightness": render.brightness,
+                "display_on": render.display_on,
+                "widgets": widgets,
+            }))
+        }
+        Command::SetText { id, text } => {
+            let widget = render
+                .widgets
+                .iter_mut()
+                .find(|w| w.id() == id)
+                .ok_or(anyhow!("组件不存在: {id}"))?;
+            let widget = widget
+                .as_any_mut()
+                .downcast_mut::<TextWidget>()
+                .ok_or(anyhow!("不是文本组件: {id}"))?;
+            // 只有静态文本(type_name="text")可以修改，其他文本组件会在下一帧被数据源覆盖
+            if widget.type_name != "text" {
+                return Err(Conflict(format!("文本组件绑定了数据源 {}，不能修改: {id}", widget.type_name)).into());
+            }
+            widget.text = text;
+            Ok(json!({ "id": id, "type_name": widget.type_name }))
+        }
+        Command::SetImage { id, data } => {
+            let widget = render
+                .widgets
+                .iter_mut()
+                .find(|w| w.id() == id)
+                .ok_or(anyhow!("组件不存在: {id}"))?;
+            let widget = widget
+                .as_any_mut()
+                .downcast_mut::<ImageWidget>()
+                .ok_or(anyhow!("不是图像组件: {id}"))?;
+            // 按照组件当前大小缩放
+            let max_size = (
+                widget.position.width().max(1) as u32,
+                widget.position.height().max(1) as u32,
+            );
+            widget.image_data = ImageData::load(&data, max_size)?;
+            widget.frame_index = 0;
+            widget.color = None;
+            widget.position.set_size(widget.image_data.width as i32, widget.image_data.height as i32);
+            Ok(json!({
+                "id": id,
+                "width": widget.image_data.width,
+                "height": widget.image_data.height,
+                "frames": widget.image_data.frames.len(),
+            }))
+        }
+        Command::SetFps(fps) => {
+            render.fps = fps;
+            Ok(json!({ "fps": fps }))
+        }
+        // 由屏幕主循环发送到设备
+        Command::SetBrightness(brightness) => {
+            render.brightness = Some(brightness);
+            Ok(json!({ "brightness": brightness }))
+        }
+        Command::SetDisplayOn(on) => {
+            render.display_on = on;
+            Ok(json!({ "on": on }))
+        }
+    }
+}
+
+fn handle_request(screens: &[ScreenHandle], mut request: Request) {
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap_or("").to_string();
+    let mut segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    // /screens/{n}/... 选择第n个screen文件，省略时使用第一个
+    let mut screen_index = 0;
+    if segments.first() == Some(&"screens") {
+        if segments.len() == 1 {
+            let list: Vec<Value> = screens
+                .iter()
+                .enumerate()
+                .map(|(idx, s)| json!({ "index": idx, "file": s.file }))
+                .collect();
+            respond_json(request, 200, &json!(list));
+            return;
+        }
+        match segments[1].parse::<usize>() {
+            Ok(idx) => screen_index = idx,
+            Err(_) => return respond_error(request, 404, "无效的屏幕序号"),
+        }
+        segments.drain(0..2);
+    }
+    let screen = match screens.get(screen_index) {
+        Some(s) => s,
+        None => return respond_error(request, 404, &format!("屏幕不存在: {screen_index}")),
+    };
+
+    if request.body_length().map(|len| len > MAX_BODY_SIZE).unwrap_or(false) {
+        return respond_error(request, 413, "请求体太大");
+    }
+    let mut body = vec![];
+    if let Err(err) = request.as_reader().take(MAX_BODY_SIZE as u64 + 1).read_to_end(&mut body) {
+        return respond_error(request, 400, &format!("读取请求失败: {err}"));
+    }
+    if body.len() > MAX_BODY_SIZE {
+        return respond_error(request, 413, "请求体太大");
+    }
+
+    let method = request.method().clone();
+    let command = match (&method, segments.as_slice()) {
+        (Method::Get, ["frame.png"]) => {
+            let frame = screen.last_frame.lock().ok().and_then(|f| f.clone());
+            match frame {
+                Some(frame) => respond_png(request, &frame),
+                None => respond_error(request, 503, "还没有渲染任何帧"),
+            }
+            return;
+        }
+        (Method::Get, ["widgets"]) => Command::ListWidgets,
+        (Method::Put | Method::Post, ["widgets", id, "text"]) => {
+            match parse_text(&body) {
+                Ok(text) => Command::SetText { id: id.to_string(), text },
+                Err(err) => return respond_error(request, 400, &format!("{err}")),
+            }
+        }
+        (Method::Put | Method::Post, ["widgets", id, "image"]) => {
+            if body.is_empty() {
+                return respond_error(request, 400, "请求体为空");
+            }
+            Command::SetImage { id: id.to_string(), data: body }
+        }
+        (Method::Put | Method::Post, ["fps"]) => match parse_fps(&body) {
+            Ok(fps) => Command::SetFps(fps),
+            Err(err) => return respond_error(request, 400, &format!("{err}")),
+        },
+        (Method::Put | Method::Post, ["brightness"]) => match parse_brightness(&body) {
+            Ok(brightness) => Command::SetBrightness(brightness),
+            Err(err) => return respond_error(request, 400, &format!("{err}")),
+        },
+        (Method::Put | Method::Post, ["display"]) => match parse_display_on(&body) {
+            Ok(on) => Command::SetDisplayOn(on),
+            Err(err) => return respond_error(request, 400, &format!("{err}")),
+        },
+        _ => return respond_error(request, 404, &format!("未知的接口: {method} {path}")),
+    };
+
+    // 发送到屏幕主循环执行
+    let (reply_sender, reply_receiver) = bounded(1);
+    if screen.sender.send_timeout((command, reply_sender), Duration::from_secs(2)).is_err() {
+        return respond_error(request, 503, "屏幕未运行");
+    }
+    match reply_receiver.recv_timeout(Duration::from_secs(5)) {
+        Ok(Ok(value)) => respond_json(request, 200, &value),
+        Ok(Err(err)) if err.is::<Conflict>() => respond_error(request, 409, &format!("{err}")),
+        Ok(Err(err)) => respond_error(request, 400, &format!("{err}")),
+        Err(_) => respond_error(request, 503, "屏幕主循环无响应"),
+    }
+}
+
+// 文本可以直接放在请求体中，或者使用JSON: {"text": "..."}
+fn parse_text(body: &[u8]) -> Result<String> {
+    let body = String::from_utf8(body.to_vec())?;
+    if let Ok(Value::Object(obj)) = serde_json::from_str::<Value>(&body) {
+        return obj
+            .get("text")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .ok_or(anyhow!("缺少text字段"));
+    }
+    Ok(body)
+}
+
+// 帧率可以直接放在请求体中，或者使用JSON: {"fps": 10}
+fn parse_fps(body: &[u8]) -> Result<f32> {
+    let body = String::from_utf8(body.to_vec())?;
+    let fps = match serde_json::from_str::<Value>(&body) {
+        Ok(Value::Object(obj)) => obj.get("fps").and_then(|v| v.as_f64()).map(|v| v as f32),
+        _ => body.trim().parse::<f32>().ok(),
+    };
+    match fps {
+        Some(fps) if fps > 0. => Ok(fps),
+        _ => Err(anyhow!("无效的帧率: {body}")),
+    }
+}
+
+// 亮度可以直接放在请求体中，或者使用JSON: {"brightness": 50}
+fn parse_brightness(body: &[u8]) -> Result<u8> {
+    let body = String::from_utf8(body.to_vec())?;
+    let brightness = match serde_json::from_str::<Value>(&body) {
+        Ok(Value::Object(obj)) => obj.get("brightness").and_then(|v| v.as_u64()),
+        _ => body.trim().parse::<u64>().ok(),
+    };
+    match brightness {
+        Some(brightness) if brightness <= 100 => Ok(brightness as u8),
+        _ => Err(anyhow!("无效的亮度(0~100): {body}")),
+    }
+}
+
+// 屏幕开关: on/off，或者使用JSON: {"on": false}
+fn parse_display_on(body: &[u8]) -> Result<bool> {
+    let body = String::from_utf8(body.to_vec())?;
+    let on = match serde_json::from_str::<Value>(&body) {
+        Ok(Value::Object(obj)) => obj.get("on").and_then(|v| v.as_bool()),
+        _ => match body.trim().to_lowercase().as_str() {
+            "on" | "1" | "true" => Some(true),
+            "off" | "0" | "false" => Some(false),
+            _ => None,
+        },
+    };
+    on.ok_or(anyhow!("无效的屏幕开关(on/off): {body}"))
+}
+
+fn respond_json(request: Request, status: u16, value: &Value) {
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(content_type("application/json; charset=utf-8"));
+    if let Err(err) = request.respond(response) {
+        error!("控制接口响应失败:{err:?}");
+    }
+}
+
+fn respond_error(request: Request, status: u16, msg: &str) {
+    respond_json(request, status, &json!({ "error": msg }));
+}
+
+fn respond_png(request: Request, frame: &RgbImage) {
+    let mut png = Cursor::new(vec![]);
+    if let Err(err) = frame.write_to(&mut png, ImageFormat::Png) {
+        return respond_error(request, 500, &format!("PNG编码失败: {err}"));
+    }
+    let response = Response::from_data(png.into_inner()).with_header(content_type("image/png"));
+    if let Err(err) = request.respond(response) {
+        error!("控制接口响应失败:{err:?}");
+    }
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).unwrap()
+}
@@ -0,0 +1,496 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use log::info;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    monitor::{self, system_uptime, Metric, Unit, WebcamInfo},
+    nmc::CITIES,
+    widgets::{ImageWidget, TextWidget, Widget},
+};
+
+// 数据源注册表: 组件的 type_name 就是数据源的名称
+// TextWidget、ProgressWidget 读取数据，ScreenRender::setup_monitor 启动数据采集，三处共用这一张表
+
+// 数据源的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataValue {
+    Text(String),
+    Metric(Metric),
+}
+
+impl DataValue {
+    pub fn format(&self, format: &ValueFormat) -> String {
+        match self {
+            DataValue::Text(text) => text.clone(),
+            DataValue::Metric(metric) => format_metric(metric, format),
+        }
+    }
+
+    // 进度条使用的百分比, 文本去掉单位后解析
+    pub fn percent(&self) -> f32 {
+        match self {
+            DataValue::Text(text) => text
+                .replace("%", "")
+                .replace("°C", "")
+                .parse::<f32>()
+                .unwrap_or(0.),
+            DataValue::Metric(metric) => metric.percent() as f32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ValueDisplay {
+    // 按照满量程换算成百分比
+    Percent,
+    // 显示数值, 有满量程时显示为 已用/总量
+    Absolute,
+}
+
+// 组件的数值格式选项, 未设置的选项使用数据源的默认格式
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ValueFormat {
+    // 小数位数
+    pub decimals: Option<usize>,
+    // 显示单位: KB/MB/GB、KB/s/MB/s、MHz/GHz、°C/°F, auto 为自动选择，单位按照原样显示
+    pub unit: Option<String>,
+    // 是否显示单位
+    pub show_unit: Option<bool>,
+    pub display: Option<ValueDisplay>,
+}
+
+impl ValueFormat {
+    // 未设置的选项使用 default 中的值
+    pub fn or(&self, default: &ValueFormat) -> ValueFormat {
+        ValueFormat {
+            decimals: self.decimals.or(default.decimals),
+            unit: self.unit.clone().or(default.unit.clone()),
+            show_unit: self.show_unit.or(default.show_unit),
+            display: self.display.or(default.display),
+        }
+    }
+}
+
+fn format_metric(metric: &Metric, format: &ValueFormat) -> String {
+    let decimals = format.decimals.unwrap_or(1);
+    let prefix = metric.label.as_ref().map(|l| format!("({l}) ")).unwrap_or_default();
+    let percent = format.display == Some(ValueDisplay::Percent) && metric.total.is_some();
+    if percent || metric.unit == Unit::Percent {
+        let value = if percent { metric.percent() } else { metric.value };
+        let unit = if format.show_unit.unwrap_or(true) { "%" } else { "" };
+        return format!("{prefix}{value:.decimals$}{unit}");
+    }
+
+    // 有满量程时按照满量程选择单位，已用和总量使用同一个单位
+    let reference = metric.total.unwrap_or(metric.value);
+    let unit = match format.unit.as_deref() {
+        None | Some("auto") => auto_unit(metric.unit, reference).to_string(),
+        Some(unit) => unit.to_string(),
+    };
+    let unit_label = if format.show_unit.unwrap_or(true) { unit.as_str() } else { "" };
+    let value = convert_unit(metric.value, metric.unit, &unit);
+    match metric.total {
+        Some(total) => {
+            let total = convert_unit(total, metric.unit, &unit);
+            format!("{prefix}{value:.decimals$}/{total:.decimals$}{unit_label}")
+        }
+        None => format!("{prefix}{value:.decimals$}{unit_label}"),
+    }
+}
+
+fn auto_unit(unit: Unit, value: f64) -> &'static str {
+    const MB: f64 = 1024. * 1024.;
+    const GB: f64 = 1024. * 1024. * 1024.;
+    match unit {
+        Unit::None => "",
+        Unit::Percent => "%",
+        Unit::Celsius => "°C",
+        Unit::Watt => "W",
+        Unit::Rpm => "RPM",
+        Unit::MHz if value >= 1000. => "GHz",
+        Unit::MHz => "MHz",
+        Unit::Bytes if value >= GB => "GB",
+        Unit::Bytes if value >= MB => "MB",
+        Unit::Bytes => "KB",
+        Unit::BytesPerSec if value >= GB => "GB/s",
+        Unit::BytesPerSec if value >= MB => "MB/s",
+        Unit::BytesPerSec => "KB/s",
+    }
+}
+
+// 把数值从监控的单位换算成显示单位，无法识别的显示单位不换算
+fn convert_unit(value: f64, unit: Unit, display_unit: &str) -> f64 {
+    let display_unit = display_unit.trim().to_uppercase();
+    match unit {
+        Unit::Bytes | Unit::BytesPerSec => {
+            let scale = match display_unit.trim_end_matches("/S") {
+                "K" | "KB" | "KIB" => 1024f64,
+                "M" | "MB" | "MIB" => 1024f64.powi(2),
+                "G" | "GB" | "GIB" => 1024f64.powi(3),
+                "T" | "TB" | "TIB" => 1024f64.powi(4),
+                _ => 1.,
+            };
+            value / scale
+        }
+        Unit::MHz => match display_unit.as_str() {
+            "GHZ" => value / 1000.,
+            "KHZ" => value * 1000.,
+            "HZ" => value * 1000. * 1000.,
+            _ => value,
+        },
+        Unit::Celsius => match display_unit.as_str() {
+            "°F" | "F" => value * 9. / 5. + 32.,
+            _ => value,
+        },
+        _ => value,
+    }
+}
+
+// 读取数据时组件提供的参数
+pub struct SourceContext<'a> {
+    // 在本类组件中，排序第几(多核CPU、多个GPU、多个磁盘)
+    pub num_widget_index: usize,
+    // 一共有多少个当前类型的组件
+    pub num_widget: usize,
+    pub tag1: &'a str,
+}
+
+// 启动数据采集时屏幕的参数
+pub struct SourceScreen {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+}
+
+type SetupFn = Box<dyn Fn(&mut dyn Widget, &SourceScreen) -> Result<()> + Send + Sync>;
+type ValueFn = Box<dyn Fn(&SourceContext) -> Option<DataValue> + Send + Sync>;
+
+pub struct DataSource {
+    pub name: String,
+    // 数值的默认格式
+    format: ValueFormat,
+    // 启动数据采集(monitor中对应的线程)，屏幕加载时对每个组件调用一次
+    setup: Option<SetupFn>,
+    // 读取当前的值，返回None时组件保持上一次的值
+    value: Option<ValueFn>,
+}
+
+impl DataSource {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), format: ValueFormat::default(), setup: None, value: None }
+    }
+
+    pub fn format(mut self, decimals: usize, unit: &str) -> Self {
+        self.format.decimals = Some(decimals);
+        self.format.unit = Some(unit.to_string());
+        self
+    }
+
+    pub fn display(mut self, display: ValueDisplay) -> Self {
+        self.format.display = Some(display);
+        self
+    }
+
+    pub fn hide_unit(mut self) -> Self {
+        self.format.show_unit = Some(false);
+        self
+    }
+
+    pub fn setup<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&mut dyn Widget, &SourceScreen) -> Result<()> + Send + Sync + 'static,
+    {
+        self.setup = Some(Box::new(f));
+        self
+    }
+
+    pub fn value<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&SourceContext) -> Option<DataValue> + Send + Sync + 'static,
+    {
+        self.value = Some(Box::new(f));
+        self
+    }
+}
+
+static REGISTRY: Lazy<RwLock<HashMap<String, Arc<DataSource>>>> = Lazy::new(|| {
+    let mut map = HashMap::new();
+    for source in builtin_sources() {
+        map.insert(source.name.clone(), Arc::new(source));
+    }
+    RwLock::new(map)
+});
+
+// 注册数据源，同名的数据源会被替换
+#[allow(unused)]
+pub fn register(source: DataSource) {
+    if let Ok(mut registry) = REGISTRY.write() {
+        info!("注册数据源:{}", source.name);
+        registry.insert(source.name.clone(), Arc::new(source));
+    }
+}
+
+fn find(name: &str) -> Option<Arc<DataSource>> {
+    REGISTRY.read().ok()?.get(name).cloned()
+}
+
+// 启动组件对应的数据采集
+pub fn setup(widget: &mut dyn Widget, screen: &SourceScreen) -> Result<()> {
+    match find(widget.data_source()).as_ref().and_then(|s| s.setup.as_ref()) {
+        Some(setup) => setup(widget, screen),
+        None => Ok(()),
+    }
+}
+
+// 读取数据源的值
+pub fn value(name: &str, ctx: &SourceContext) -> Option<DataValue> {
+    let source = find(name)?;
+    source.value.as_ref().and_then(|value| value(ctx))
+}
+
+// 数据源的默认格式
+pub fn default_format(name: &str) -> ValueFormat {
+    find(name).map(|s| s.format.clone()).unwrap_or_default()
+}
+
+fn indexed_or_first<F>(index: usize, getter: F) -> Option<Metric>
+where
+    F: Fn(usize) -> Option<Metric>,
+{
+    getter(index).or_else(|| {
+        if index == 0 {
+            None
+        } else {
+            getter(0)
+        }
+    })
+}
+
+fn text(text: Option<String>) -> Option<DataValue> {
+    text.map(DataValue::Text)
+}
+
+fn metric(metric: Option<Metric>) -> Option<DataValue> {
+    metric.map(DataValue::Metric)
+}
+
+fn builtin_sources() -> Vec<DataSource> {
+    vec![
+        DataSource::new("cpu")
+            .setup(|_, _| monitor::watch_cpu(true))
+            .value(|_| text(monitor::cpu_brand())),
+        DataSource::new("cpu_usage")
+            .setup(|_, _| monitor::watch_cpu(true))
+            .format(1, "%")
+            .value(|ctx| {
+                if ctx.num_widget == 1 {
+                    metric(monitor::cpu_usage())
+                } else {
+                    metric(monitor::cpu_usage_percpu(ctx.num_widget_index))
+                }
+            }),
+        // 所有核心的使用率由 CpuCoresWidget 直接读取
+        DataSource::new("cpu_cores").setup(|_, _| monitor::watch_cpu(true)),
+        DataSource::new("num_cpu")
+            .setup(|_, _| monitor::watch_cpu(true))
+            .format(0, "")
+            .value(|_| metric(monitor::num_cpus())),
+        DataSource::new("cpu_freq")
+            .setup(|_, _| monitor::watch_cpu_clock_speed(true))
+            .format(2, " GHz")
+            .value(|_| metric(monitor::cpu_clock_speed(None))),
+        DataSource::new("cpu_temp.")
+            .setup(|_, _| monitor::watch_cpu_temperatures(true))
+            .format(1, "°C")
+            .value(|_| metric(monitor::cpu_temperature())),
+        DataSource::new("cpu_cores_power")
+            .setup(|_, _| monitor::watch_cpu_power(true))
+            .format(1, "W")
+            .value(|_| metric(monitor::cpu_cores_power())),
+        DataSource::new("cpu_package_power")
+            .setup(|_, _| monitor::watch_cpu_power(true))
+            .format(1, "W")
+            .value(|_| metric(monitor::cpu_package_power())),
+        DataSource::new("cpu_fan")
+            .setup(|_, _| monitor::watch_cpu_fan(true))
+            .format(0, "RPM")
+            .value(|_| metric(monitor::cpu_fan())),
+        DataSource::new("memory")
+            .setup(|_, _| monitor::watch_memory(true))
+            .format(1, "GB")
+            .display(ValueDisplay::Absolute)
+            .value(|_| metric(monitor::memory())),
+        DataSource::new("memory_total")
+            .setup(|_, _| monitor::watch_memory(true))
+            .format(1, "G")
+            .value(|_| metric(monitor::memory_total())),
+        DataSource::new("memory_percent")
+            .setup(|_, _| monitor::watch_memory(true))
+            .format(0, "GB")
+            .display(ValueDisplay::Percent)
+            .value(|_| metric(monitor::memory())),
+        DataSource::new("swap")
+            .setup(|_, _| monitor::watch_memory(true))
+            .format(1, "GB")
+            .display(ValueDisplay::Absolute)
+            .value(|_| metric(monitor::swap())),
+        DataSource::new("swap_percent")
+            .setup(|_, _| monitor::watch_memory(true))
+            .format(0, "GB")
+            .display(ValueDisplay::Percent)
+            .value(|_| metric(monitor::swap())),
+        DataSource::new("gpu_fan")
+            .setup(|_, _| monitor::watch_gpu_fan(true))
+            .format(1, "RPM")
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::gpu_fan))),
+        DataSource::new("gpu_clock")
+            .setup(|_, _| monitor::watch_gpu_clock_speed(true))
+            .format(1, " GHz")
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::gpu_clocks))),
+        DataSource::new("gpu_load")
+            .setup(|_, _| monitor::watch_gpu_load(true))
+            .format(1, "%")
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::gpu_load))),
+        DataSource::new("gpu_memory_load")
+            .setup(|_, _| monitor::watch_gpu_load(true))
+            .format(1, "%")
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::gpu_memory_load))),
+        DataSource::new("gpu_memory_total_mb")
+            .setup(|_, _| monitor::watch_gpu_load(true))
+            .format(1, "MB")
+            .hide_unit()
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::gpu_memory_total))),
+        DataSource::new("gpu_memory_total_gb")
+            .setup(|_, _| monitor::watch_gpu_load(true))
+            .format(1, "GB")
+            .hide_unit()
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::gpu_memory_total))),
+        DataSource::new("gpu_temp.")
+            .setup(|_, _| monitor::watch_gpu_temperatures(true))
+            .format(1, "°C")
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::gpu_temperature))),
+        DataSource::new("gpu_cores_power")
+            .setup(|_, _| monitor::watch_cpu_power(true))
+            .format(1, "W")
+            .value(|_| metric(monitor::gpu_cores_power())),
+        DataSource::new("gpu_package_power")
+            .setup(|_, _| monitor::watch_cpu_power(true))
+            .format(1, "W")
+            .value(|_| metric(monitor::gpu_package_power())),
+        DataSource::new("num_process")
+            .setup(|_, _| monitor::watch_process(true))
+            .format(0, "")
+            .value(|_| metric(monitor::num_process())),
+        DataSource::new("disk_usage")
+            .setup(|_, _| monitor::watch_disk(true))
+            .format(1, "GB")
+            .display(ValueDisplay::Absolute)
+            .value(|ctx| metric(indexed_or_first(ctx.num_widget_index, monitor::disk_usage))),
+        DataSource::new("disk_read_speed")
+            .setup(|_, _| monitor::watch_disk_speed(true))
+            .format(1, " MB/s")
+            .value(|_| metric(monitor::disk_speed_per_sec().map(|(r, _w)| r))),
+        DataSource::new("disk_write_speed")
+            .setup(|_, _| monitor::watch_disk_speed(true))
+            .format(1, " MB/s")
+            .value(|_| metric(monitor::disk_speed_per_sec().map(|(_r, w)| w))),
+        DataSource::new("received_speed")
+            .setup(|_, _| monitor::watch_network_speed(true))
+            .format(1, "auto")
+            .value(|_| metric(monitor::network_speed_per_sec().map(|(r, _t)| r))),
+        DataSource::new("transmitted_speed")
+            .setup(|_, _| monitor::watch_network_speed(true))
+            .format(1, "auto")
+            .value(|_| metric(monitor::network_speed_per_sec().map(|(_r, t)| t))),
+        DataSource::new("net_ip")
+            .setup(|_, _| monitor::watch_net_ip(true))
+            .value(|_| text(monitor::net_ip_address())),
+        DataSource::new("net_ip_info")
+            .setup(|_, _| monitor::watch_net_ip(true))
+            .value(|_| text(monitor::net_ip_info())),
+        DataSource::new("local_ip").value(|_| text(monitor::local_ip_addresses())),
+        DataSource::new("system").value(|_| text(monitor::system_name())),
+        DataSource::new("version").value(|_| text(monitor::os_version())),
+        DataSource::new("kernel").value(|_| text(monitor::kernel_version())),
+        DataSource::new("host").value(|_| text(monitor::host_name())),
+        DataSource::new("date").value(|_| text(Some(monitor::date()))),
+        DataSource::new("time").value(|_| text(Some(monitor::time()))),
+        DataSource::new("weekday").value(|_| text(Some(monitor::chinese_weekday()))),
+        DataSource::new("lunar_year").value(|_| text(Some(monitor::lunar_year()))),
+        DataSource::new("lunar_date").value(|_| text(Some(monitor::lunar_date()))),
+        DataSource::new("uptime").format(0, "").value(|ctx| {
+            let uptime = system_uptime();
+            let v = match ctx.tag1 {
+                //运行分钟数
+                "1" => uptime.minutes,
+                //运行小时数
+                "2" => uptime.hours,
+                //运行天数
+                "3" => uptime.days,
+                //运行秒数
+                _ => uptime.seconds,
+            };
+            Some(DataValue::Metric(Metric::new(v as f64, Unit::None)))
+        }),
+        DataSource::new("weather")
+            .setup(|widget, _| {
+                if let Some(widget) = widget.as_any_mut().downcast_mut::<TextWidget>() {
+                    if !widget.tag2.is_empty() {
+                        //查询对应的城市
+                        info!("更新天气，查询对应的城市: tag2={}", widget.tag2);
+                        if let Some(city) = CITIES.iter().find(|c| c.city == widget.tag2) {
+                            monitor::watch_weather(Some(city.clone()))?
+                        }
+                    }
+                }
+                Ok(())
+            })
+            .value(|ctx| match monitor::weather_info() {
+                None => None,
+                Some(w) => Some(DataValue::Text(match ctx.tag1 {
+                    "1" => w.station.city.clone(),                 //城市
+                    "2" => format!("{}℃", w.weather.temperature), //气温
+                    "3" => format!("{}℃", w.wind.direct),         //风向
+                    "4" => w.wind.power.clone(),                   //风力
+                    "5" => format!("{}级", w.wind.speed),         //风级
+                    "6" => w.weather.img.clone(),                  //图标
+                    _ => w.weather.info.clone(),
+                })),
+            }),
+        DataSource::new("webcam").setup(|widget, screen| {
+            if let Some(widget) = widget.as_any_mut().downcast_mut::<ImageWidget>() {
+                info!("webcam: tag1={:?}", widget.tag1);
+                monitor::watch_webcam(Some(WebcamInfo {
+                    width: screen.width,
+                    height: screen.height,
+                    index: widget.tag1.as_ref().unwrap_or(&String::new()).parse().unwrap_or(0),
+                    fps: screen.fps as u32,
+                }))?
+            }
+            Ok(())
+        }),
+    ]
+}
+
+#[test]
+fn test_format_metric() {
+    const GB: f64 = 1024. * 1024. * 1024.;
+    let memory = DataValue::Metric(Metric::new(6. * GB, Unit::Bytes).with_total(16. * GB));
+    assert_eq!(memory.format(&default_format("memory")), "6.0/16.0GB");
+    assert_eq!(memory.format(&default_format("memory_percent")), "38%");
+    let format = ValueFormat { decimals: Some(2), unit: Some("MB".to_string()), ..Default::default() };
+    assert_eq!(memory.format(&format.or(&default_format("memory"))), "6144.00/16384.00MB");
+
+    let speed = DataValue::Metric(Metric::new(1.5 * 1024. * 1024., Unit::BytesPerSec));
+    assert_eq!(speed.format(&default_format("received_speed")), "1.5MB/s");
+    let freq = DataValue::Metric(Metric::new(2100., Unit::MHz));
+    assert_eq!(freq.format(&default_format("cpu_freq")), "2.10 GHz");
+    assert_eq!(freq.format(&ValueFormat { decimals: Some(0), unit: Some("MHz".to_string()), ..Default::default() }), "2100MHz");
+}
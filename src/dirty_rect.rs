@@ -0,0 +1,156 @@
+use image::RgbImage;
+
+// 脏矩形: 比较前后两帧，找出发生变化的区域，屏幕只需要更新这些区域
+// 先按块比较，再把相邻的变化块合并成矩形，矩形太多时合并成更大的矩形
+
+// 按照16x16的块比较
+const TILE_SIZE: u32 = 16;
+// 变化的面积超过整帧的一半时直接发送整帧
+const FULL_FRAME_RATIO: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DirtyRect {
+    fn area(&self) -> u32 {
+        self.width * self.height
+    }
+
+    fn union(&self, other: &DirtyRect) -> DirtyRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        DirtyRect { x, y, width: right - x, height: bottom - y }
+    }
+}
+
+// 返回变化的区域，最多 max_rects 个，两帧完全相同时返回空列表
+// 两帧尺寸不同或者变化太多时返回整帧
+pub fn diff(prev: &RgbImage, cur: &RgbImage, max_rects: usize) -> Vec<DirtyRect> {
+    let (width, height) = cur.dimensions();
+    let full = DirtyRect { x: 0, y: 0, width, height };
+    if prev.dimensions() != cur.dimensions() {
+        return vec![full];
+    }
+
+    let cols = width.div_ceil(TILE_SIZE);
+    let rows = height.div_ceil(TILE_SIZE);
+    let row_bytes = width as usize * 3;
+    let (prev_raw, cur_raw) = (prev.as_raw(), cur.as_raw());
+
+    // 每一行块中连续变化的块合并成一段，和上一行相同的段合并成矩形(块为单位)
+    let mut rects: Vec<DirtyRect> = vec![];
+    let mut open: Vec<usize> = vec![];
+    for row in 0..rows {
+        let mut spans = vec![];
+        let mut start = None;
+        for col in 0..=cols {
+            let dirty = col < cols && {
+                let x0 = (col * TILE_SIZE) as usize * 3;
+                let x1 = ((col + 1) * TILE_SIZE).min(width) as usize * 3;
+                (row * TILE_SIZE..((row + 1) * TILE_SIZE).min(height)).any(|y| {
+                    let offset = y as usize * row_bytes;
+                    prev_raw[offset + x0..offset + x1] != cur_raw[offset + x0..offset + x1]
+                })
+            };
+            match (dirty, start) {
+                (true, None) => start = Some(col),
+                (false, Some(s)) => {
+                    spans.push((s, col));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        let mut next_open = vec![];
+        for (c0, c1) in spans {
+            let extend = open.iter().copied().find(|idx| {
+                let r = &rects[*idx];
+                r.x == c0 && r.width == c1 - c0
+            });
+            match extend {
+                Some(idx) => {
+                    rects[idx].height += 1;
+                    next_open.push(idx);
+                }
+                None => {
+                    rects.push(DirtyRect { x: c0, y: row, width: c1 - c0, height: 1 });
+                    next_open.push(rects.len() - 1);
+                }
+            }
+        }
+        open = next_open;
+    }
+
+    // 转换成像素坐标
+    let mut rects: Vec<DirtyRect> = rects
+        .into_iter()
+        .map(|r| {
+            let x = r.x * TILE_SIZE;
+            let y = r.y * TILE_SIZE;
+            DirtyRect {
+                x,
+                y,
+                width: ((r.x + r.width) * TILE_SIZE).min(width) - x,
+                height: ((r.y + r.height) * TILE_SIZE).min(height) - y,
+            }
+        })
+        .collect();
+
+    // 每次合并增加面积最少的两个矩形
+    while rects.len() > max_rects.max(1) {
+        let mut best = (0, 1, u32::MAX);
+        for i in 0..rects.len() {
+            for j in i + 1..rects.len() {
+                let cost = rects[i].union(&rects[j]).area() - rects[i].area() - rects[j].area();
+                if cost < best.2 {
+                    best = (i, j, cost);
+                }
+            }
+        }
+        let (i, j, _) = best;
+        let merged = rects[i].union(&rects[j]);
+        rects.swap_remove(j);
+        rects[i] = merged;
+    }
+
+    let dirty_area: u32 = rects.iter().map(|r| r.area()).sum();
+    if dirty_area as f32 > full.area() as f32 * FULL_FRAME_RATIO {
+        return vec![full];
+    }
+    rects
+}
+
+#[test]
+fn test_diff() {
+    let prev = RgbImage::new(320, 240);
+    assert!(diff(&prev, &prev, 4).is_empty());
+
+    // 时钟区域变化
+    let mut cur = prev.clone();
+    for x in 100..140 {
+        for y in 20..30 {
+            cur.put_pixel(x, y, image::Rgb([255, 255, 255]));
+        }
+    }
+    let rects = diff(&prev, &cur, 4);
+    assert_eq!(rects, vec![DirtyRect { x: 96, y: 16, width: 48, height: 16 }]);
+
+    // 两个角落变化，限制为1个矩形时合并
+    let mut cur = prev.clone();
+    cur.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+    cur.put_pixel(40, 40, image::Rgb([255, 0, 0]));
+    assert_eq!(diff(&prev, &cur, 4).len(), 2);
+    assert_eq!(diff(&prev, &cur, 1), vec![DirtyRect { x: 0, y: 0, width: 48, height: 48 }]);
+
+    // 尺寸不同时返回整帧
+    let cur = RgbImage::new(160, 128);
+    assert_eq!(diff(&prev, &cur, 4), vec![DirtyRect { x: 0, y: 0, width: 160, height: 128 }]);
+}
@@ -23,18 +23,19 @@ use std::{
     str::FromStr,
 };
 use async_std::sync::Arc;
-use crate::{utils, wifi_screen};
+use crate::{utils, wifi_screen::{self, WifiScreen}};
 use crate::usb_screen::{self, UsbScreen, UsbScreenInfo};
 use crate::{
+    data_source::ValueDisplay,
     nmc::CITIES,
     screen::{ScreenRender, ScreenSize, DEFAULT_FONT},
     utils::get_font_name,
-    widgets::{ImageData, ImageWidget, ProgressWidget, ProgressType, TextWidget, Widget},
+    widgets::{ChartType, ChartWidget, CoresLayout, CpuCoresWidget, ImageData, ImageWidget, Orientation, ProgressWidget, ProgressType, TextWidget, Widget},
 };
 
 enum CurrentScreen{
     USBScreen(CurrentUsbScreen),
-    WiFiScreen(String),
+    WiFiScreen(String, WifiScreen),
 }
 struct CurrentUsbScreen{
     info: UsbScreenInfo,
@@ -58,9 +59,9 @@ impl CurrentScreen{
                 usb.screen = screen;
                 ret
             }
-            Self::WiFiScreen(_) => {
+            Self::WiFiScreen(_, wifi) => {
                 let img: RgbaImage = img.convert();
-                wifi_screen::send_message(wifi_screen::Message::Image(img))
+                wifi.send_message(wifi_screen::Message::Image(img))
             }
         }
     }
@@ -242,7 +243,7 @@ impl CanvasEditorContext {
             //如果已经连接了wifi屏幕，不再刷新
             {
                 let current_screen = self.current_screen.lock().await;
-                if let Some(&CurrentScreen::WiFiScreen(_)) = current_screen.as_ref(){
+                if let Some(&CurrentScreen::WiFiScreen(..)) = current_screen.as_ref(){
                     info!("wifi屏幕已打开,不再自动连接设备");
                     return;
                 }
@@ -487,6 +488,14 @@ impl CanvasEditorContext {
             widget.height = nh;
             widget.position.set_size(nw, nh);
         }
+
+        if let Some(widget) = widget.as_any_mut().downcast_mut::<ChartWidget>() {
+            widget.position.set_size(nw, nh);
+        }
+
+        if let Some(widget) = widget.as_any_mut().downcast_mut::<CpuCoresWidget>() {
+            widget.position.set_size(nw, nh);
+        }
     }
 
     fn on_update_widget_text(&mut self) {
@@ -680,6 +689,26 @@ impl CanvasEditorContext {
         }
     }
 
+    fn on_update_widget_format(&mut self){
+        let app = self.app.unwrap();
+        let decimals = app.get_active_widget_format_decimals();
+        let unit = app.get_active_widget_format_unit();
+        let display = app.get_active_widget_format_display();
+
+        if let Some(widget) = self
+            .active_widget()
+            .and_then(|w| w.as_any_mut().downcast_mut::<TextWidget>())
+        {
+            widget.format.decimals = decimals.trim().parse().ok();
+            widget.format.unit = if unit.trim().len() > 0 { Some(unit.to_string()) } else { None };
+            widget.format.display = match display.as_str() {
+                "百分比" => Some(ValueDisplay::Percent),
+                "数值" => Some(ValueDisplay::Absolute),
+                _ => None,
+            };
+        }
+    }
+
     /// 更新进度条配置
     fn on_update_progress_config(&mut self) {
         let app = self.app.unwrap();
@@ -724,6 +753,90 @@ impl CanvasEditorContext {
         }
     }
 
+    /// 更新图表配置
+    fn on_update_chart_config(&mut self) {
+        let app = self.app.unwrap();
+        if let Some(widget) = self
+            .active_widget()
+            .and_then(|w| w.as_any_mut().downcast_mut::<ChartWidget>())
+        {
+            // 切换数据源时恢复默认的纵轴范围
+            let data_source = app.get_active_chart_data_source().to_string();
+            if widget.source != data_source {
+                widget.source = data_source;
+                widget.set_default_range();
+                app.set_active_chart_min(format_chart_range(widget.min));
+                app.set_active_chart_max(format_chart_range(widget.max));
+            } else {
+                widget.min = app.get_active_chart_min().trim().parse::<f32>().ok();
+                widget.max = app.get_active_chart_max().trim().parse::<f32>().ok();
+            }
+
+            if let Ok(secs) = app.get_active_chart_window().trim().parse::<u32>() {
+                widget.window_secs = secs.max(1);
+            }
+            if let Ok(w) = app.get_active_chart_line_width().trim().parse::<f32>() {
+                widget.line_width = w.max(0.5);
+            }
+            if let Ok(c) = HexColor::parse(app.get_active_chart_line_color().trim()) {
+                widget.line_color = [c.r, c.g, c.b, c.a];
+            }
+            if let Ok(c) = HexColor::parse(app.get_active_chart_fill_color().trim()) {
+                widget.fill_color = [c.r, c.g, c.b, c.a];
+            }
+            // 背景色为空时透明
+            let bg_str = app.get_active_chart_bg_color().trim().to_string();
+            if bg_str.is_empty() {
+                widget.background_color = [0, 0, 0, 0];
+            } else if let Ok(c) = HexColor::parse(&bg_str) {
+                widget.background_color = [c.r, c.g, c.b, c.a];
+            }
+            widget.chart_type = if app.get_active_chart_area() {
+                ChartType::Area
+            } else {
+                ChartType::Line
+            };
+
+            let _ = self.screen.setup_monitor();
+        }
+    }
+
+    /// 更新CPU核心组件配置
+    fn on_update_cores_config(&mut self) {
+        let app = self.app.unwrap();
+        if let Some(widget) = self
+            .active_widget()
+            .and_then(|w| w.as_any_mut().downcast_mut::<CpuCoresWidget>())
+        {
+            widget.layout = if app.get_active_cores_layout() == 1 {
+                CoresLayout::Grid
+            } else {
+                CoresLayout::Bars
+            };
+            widget.orientation = if app.get_active_cores_orientation() == 1 {
+                Orientation::Horizontal
+            } else {
+                Orientation::Vertical
+            };
+            // 列数为空时自动
+            let columns = app.get_active_cores_columns();
+            if columns.trim().is_empty() {
+                widget.columns = 0;
+            } else if let Ok(columns) = columns.trim().parse::<usize>() {
+                widget.columns = columns;
+            }
+            if let Ok(gap) = app.get_active_cores_gap().trim().parse::<i32>() {
+                widget.gap = gap.max(0);
+            }
+            if let Ok(c) = HexColor::parse(app.get_active_cores_fg_color().trim()) {
+                widget.foreground_color = [c.r, c.g, c.b, c.a];
+            }
+            if let Ok(c) = HexColor::parse(app.get_active_cores_bg_color().trim()) {
+                widget.background_color = [c.r, c.g, c.b, c.a];
+            }
+        }
+    }
+
     fn on_update_widget_tags(&mut self) {
         let app = self.app.unwrap();
         let tag1 = app.get_active_widget_tag1();
@@ -889,6 +1002,55 @@ impl CanvasEditorContext {
             return;
         }
 
+        // 图表组件
+        if let Some(widget) = self
+            .active_widget()
+            .and_then(|w| w.as_any_mut().downcast_mut::<ChartWidget>())
+        {
+            app.set_active_widget_type_name("line_chart".into());
+            app.set_active_widget_uuid(SharedString::from(&widget.id));
+            app.set_active_widget_x(format!("{}", widget.position.center().0).into());
+            app.set_active_widget_y(format!("{}", widget.position.center().1).into());
+            app.set_active_widget_width(format!("{}", widget.position.width()).into());
+            app.set_active_widget_height(format!("{}", widget.position.height()).into());
+
+            app.set_active_chart_data_source(widget.source.as_str().into());
+            app.set_active_chart_window(format!("{}", widget.window_secs).into());
+            app.set_active_chart_min(format_chart_range(widget.min));
+            app.set_active_chart_max(format_chart_range(widget.max));
+            app.set_active_chart_line_color(format_rgba_hex(widget.line_color));
+            app.set_active_chart_fill_color(format_rgba_hex(widget.fill_color));
+            app.set_active_chart_bg_color(if widget.background_color[3] == 0 {
+                SharedString::new()
+            } else {
+                format_rgba_hex(widget.background_color)
+            });
+            app.set_active_chart_line_width(format!("{}", widget.line_width).into());
+            app.set_active_chart_area(widget.chart_type == ChartType::Area);
+            return;
+        }
+
+        // CPU核心组件
+        if let Some(widget) = self
+            .active_widget()
+            .and_then(|w| w.as_any_mut().downcast_mut::<CpuCoresWidget>())
+        {
+            app.set_active_widget_type_name("cpu_cores".into());
+            app.set_active_widget_uuid(SharedString::from(&widget.id));
+            app.set_active_widget_x(format!("{}", widget.position.center().0).into());
+            app.set_active_widget_y(format!("{}", widget.position.center().1).into());
+            app.set_active_widget_width(format!("{}", widget.position.width()).into());
+            app.set_active_widget_height(format!("{}", widget.position.height()).into());
+
+            app.set_active_cores_layout(if widget.layout == CoresLayout::Grid { 1 } else { 0 });
+            app.set_active_cores_orientation(if widget.orientation == Orientation::Horizontal { 1 } else { 0 });
+            app.set_active_cores_columns(format!("{}", widget.columns).into());
+            app.set_active_cores_gap(format!("{}", widget.gap).into());
+            app.set_active_cores_fg_color(format_rgba_hex(widget.foreground_color));
+            app.set_active_cores_bg_color(format_rgba_hex(widget.background_color));
+            return;
+        }
+
         self.update_widget_edit_text();
 
         if let Some(id) = self.active_id.as_ref() {
@@ -977,6 +1139,13 @@ impl CanvasEditorContext {
         app.set_active_widget_prop_width(SharedString::from(&widget.width.map(|i| format!("{i}")).unwrap_or(String::new())));
         app.set_active_widget_prop_height(SharedString::from(&widget.height.map(|i| format!("{i}")).unwrap_or(String::new())));
         app.set_active_widget_prop_alignment(SharedString::from(&widget.alignment.clone().unwrap_or(String::new())));
+        app.set_active_widget_format_decimals(SharedString::from(&widget.format.decimals.map(|i| format!("{i}")).unwrap_or(String::new())));
+        app.set_active_widget_format_unit(SharedString::from(widget.format.unit.as_ref().unwrap_or(&String::new())));
+        app.set_active_widget_format_display(SharedString::from(match widget.format.display {
+            Some(ValueDisplay::Percent) => "百分比",
+            Some(ValueDisplay::Absolute) => "数值",
+            None => "默认",
+        }));
         app.set_active_widget_font_size(format!("{}", widget.font_size as i32).into());
         app.set_active_widget_prefix(SharedString::from(&widget.prefix));
         app.set_active_widget_color(Color::from_argb_u8(
@@ -1201,6 +1370,8 @@ impl CanvasEditorContext {
         let mut text_widget_clone = None;
         let mut image_widget_clone = None;
         let mut progress_widget_clone = None;
+        let mut chart_widget_clone = None;
+        let mut cores_widget_clone = None;
 
         if let Some(ref_text_widget) = self.screen.widgets[widget_index].as_any_mut().downcast_mut::<TextWidget>() {
             text_widget_clone = Some(ref_text_widget.clone());
@@ -1211,6 +1382,12 @@ impl CanvasEditorContext {
         if let Some(ref_progress_widget) = self.screen.widgets[widget_index].as_any_mut().downcast_mut::<ProgressWidget>() {
             progress_widget_clone = Some(ref_progress_widget.clone());
         }
+        if let Some(ref_chart_widget) = self.screen.widgets[widget_index].as_any_mut().downcast_mut::<ChartWidget>() {
+            chart_widget_clone = Some(ref_chart_widget.clone());
+        }
+        if let Some(ref_cores_widget) = self.screen.widgets[widget_index].as_any_mut().downcast_mut::<CpuCoresWidget>() {
+            cores_widget_clone = Some(ref_cores_widget.clone());
+        }
 
         if let Some((idx, w)) = self.screen.find_widget(&uuid) {
 
@@ -1230,6 +1407,14 @@ impl CanvasEditorContext {
                 *progress_widget = progress_widget_clone.unwrap();
                 progress_widget.id = uuid.clone();
             }
+            if let Some(chart_widget) = w.as_any_mut().downcast_mut::<ChartWidget>() {
+                *chart_widget = chart_widget_clone.unwrap();
+                chart_widget.id = uuid.clone();
+            }
+            if let Some(cores_widget) = w.as_any_mut().downcast_mut::<CpuCoresWidget>() {
+                *cores_widget = cores_widget_clone.unwrap();
+                cores_widget.id = uuid.clone();
+            }
 
             w.position_mut().offset(5, 5);
 
@@ -1464,14 +1649,16 @@ impl CanvasEditorContext {
         match current_device.as_ref(){
             None => {
                 self.screen.device_ip = None;
+                self.screen.device_id = None;
             }
             Some(screen) => {
                 match screen{
-                    CurrentScreen::WiFiScreen(ip) => {
+                    CurrentScreen::WiFiScreen(ip, _) => {
                         self.screen.device_ip = Some(ip.to_string());
                     },
                     CurrentScreen::USBScreen(screen) => {
                         self.screen.device_ip = None;
+                        self.screen.device_id = None;
                         if screen.info.width == self.screen.width as u16 && screen.info.height == self.screen.height as u16{
                             self.screen.device_address = Some(screen.info.address.clone());
                             size_fit = true;
@@ -1840,32 +2027,52 @@ impl CanvasEditorContext {
         let app_c = app.as_weak();
         //尝试连接屏幕
         let device_ip_clone = device_ip.clone();
+        let security = self.screen.wifi_security.clone();
         let test_ret = spawn_blocking(move ||{
-            wifi_screen::test_screen_sync(device_ip_clone)
+            wifi_screen::test_screen_sync(device_ip_clone, &security)
         }).await;
         let success = match test_ret{
             Ok(()) => {
-                //测试连接成功
+                //测试连接成功，记录屏幕的设备ID，屏幕换了IP后运行时可以自动找到
+                let device_ip_clone = device_ip.clone();
+                //老固件不回复发现请求，没有设备ID
+                self.screen.device_id = spawn_blocking(move ||{
+                    let screens = wifi_screen::discover(wifi_screen::DISCOVERY_TIMEOUT).unwrap_or_default();
+                    screens.into_iter().find(|s| s.ip == device_ip_clone).map(|s| s.id)
+                }).await;
+                info!("WiFi屏幕设备ID:{:?}", self.screen.device_id);
                 let mut screen = self.current_screen.lock().await;
                 //关闭USB屏幕
                 if let Some(CurrentScreen::USBScreen(_)) = screen.as_ref() {
                     let _ = screen.take();
                 }
 
-                //开始连接屏幕
-                let _ = wifi_screen::send_message(wifi_screen::Message::Connect(device_ip.clone()));
-                screen.replace(CurrentScreen::WiFiScreen(device_ip.clone()));
-                let app_c_clone = app_c.clone();
-                for _ in 0..10{
-                    spawn_blocking(move ||{ std::thread::sleep(Duration::from_secs(1)) }).await;
-                    if let Ok(status) = wifi_screen::get_status(){
-                        if let wifi_screen::Status::Connected = status.status{
-                            toast(app_c_clone, "连接成功!");
-                            break;
+                //开始连接屏幕，之前连接的WiFi屏幕会断开
+                match WifiScreen::new(){
+                    Ok(wifi) => {
+                        let _ = wifi.send_message(wifi_screen::Message::Scaling(self.screen.scaling));
+                        let _ = wifi.send_message(wifi_screen::Message::Codec(self.screen.wifi_codec));
+                        let _ = wifi.send_message(wifi_screen::Message::Security(self.screen.wifi_security.clone()));
+                        let _ = wifi.send_message(wifi_screen::Message::Connect(device_ip.clone()));
+                        screen.replace(CurrentScreen::WiFiScreen(device_ip.clone(), wifi.clone()));
+                        let app_c_clone = app_c.clone();
+                        for _ in 0..10{
+                            spawn_blocking(move ||{ std::thread::sleep(Duration::from_secs(1)) }).await;
+                            if let Ok(status) = wifi.get_status(){
+                                if let wifi_screen::Status::Connected = status.status{
+                                    toast(app_c_clone, "连接成功!");
+                                    break;
+                                }
+                            }
                         }
+                        true
+                    }
+                    Err(err) => {
+                        let msg = format!("WiFi屏幕连接失败:{}", err.root_cause());
+                        toast(app_c.clone(), &msg.as_str());
+                        false
                     }
                 }
-                true
             }
             Err(err) =>{
                 let msg = format!("WiFi屏幕连接失败:{}", err.root_cause());
@@ -1886,6 +2093,7 @@ impl CanvasEditorContext {
         //清空IP
         self.app.unwrap().set_device_ip("".into());
         self.screen.device_ip = None;
+        self.screen.device_id = None;
         let devices = self.devices.clone();
         let app_clone = self.app.clone();
         let mut screen = self.current_screen.lock().await;
@@ -1893,8 +2101,8 @@ impl CanvasEditorContext {
         for dev in devices{
             if device.as_str().contains(&dev.label){
                 //================== 关闭wifi屏幕 ================
-                if let Some(CurrentScreen::WiFiScreen(_)) = screen.as_ref(){
-                    if let Err(err) = wifi_screen::send_message(wifi_screen::Message::Disconnect){
+                if let Some(CurrentScreen::WiFiScreen(_, wifi)) = screen.as_ref(){
+                    if let Err(err) = wifi.send_message(wifi_screen::Message::Disconnect){
                         error!("断开连接出错:{err:?}");
                     }
                     let _ = screen.take();
@@ -1947,6 +2155,20 @@ fn format_screen_name_label(s: &ScreenSize) -> String{
     )
 }
 
+// 图表纵轴范围, 自动缩放时为空
+fn format_chart_range(v: Option<f32>) -> SharedString {
+    v.map(|v| format!("{v}")).unwrap_or_default().into()
+}
+
+// 不透明时输出 #RRGGBB, 否则输出 #RRGGBBAA
+fn format_rgba_hex(c: [u8; 4]) -> SharedString {
+    if c[3] == 255 {
+        format!("#{:02X}{:02X}{:02X}", c[0], c[1], c[2]).into()
+    } else {
+        format!("#{:02X}{:02X}{:02X}{:02X}", c[0], c[1], c[2], c[3]).into()
+    }
+}
+
 pub fn run() -> Result<()> {
     let app = CanvasEditor::new().unwrap();
     let mut context = CanvasEditorContext::new(app.as_weak());
@@ -2142,6 +2364,13 @@ pub fn run() -> Result<()> {
         }
     });
 
+    let context_clone = context.clone();
+    app.on_update_widget_format(move || {
+        if let Ok(mut context) = context_clone.try_borrow_mut(){
+            context.on_update_widget_format();
+        }
+    });
+
     let context_clone = context.clone();
     app.on_update_progress_config(move || {
         if let Ok(mut context) = context_clone.try_borrow_mut(){
@@ -2149,6 +2378,20 @@ pub fn run() -> Result<()> {
         }
     });
 
+    let context_clone = context.clone();
+    app.on_update_chart_config(move || {
+        if let Ok(mut context) = context_clone.try_borrow_mut(){
+            context.on_update_chart_config();
+        }
+    });
+
+    let context_clone = context.clone();
+    app.on_update_cores_config(move || {
+        if let Ok(mut context) = context_clone.try_borrow_mut(){
+            context.on_update_cores_config();
+        }
+    });
+
     let context_clone = context.clone();
     app.on_update_progress_fg_color(move || {
         if let Ok(mut context) = context_clone.try_borrow_mut(){
@@ -0,0 +1,110 @@
+use std::{fs::File, path::{Path, PathBuf}, time::{Duration, Instant}};
+
+use anyhow::Result;
+use clap::Args;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbImage,
+};
+use log::info;
+
+use crate::{cli::ScreenOverrides, monitor, screen::ScreenRender};
+
+// 无头渲染参数: 不连接任何设备，把帧写入PNG序列或者GIF动画
+#[derive(Args)]
+pub struct RenderOptions {
+    /// .screen 文件路径
+    pub file: String,
+    /// 渲染的帧数
+    #[arg(short = 'n', long)]
+    pub frames: Option<u32>,
+    /// 渲染时长(秒)，按照帧率换算成帧数
+    #[arg(short, long, conflicts_with = "frames")]
+    pub seconds: Option<f32>,
+    /// 以 .gif 结尾时输出GIF动画，否则作为PNG序列的输出目录
+    #[arg(short, long, default_value = "frames")]
+    pub output: PathBuf,
+    #[command(flatten)]
+    pub overrides: ScreenOverrides,
+}
+
+impl RenderOptions {
+    fn frame_count(&self, fps: f32) -> u32 {
+        match (self.frames, self.seconds) {
+            (Some(frames), _) => frames,
+            (None, Some(seconds)) => (seconds * fps).ceil() as u32,
+            (None, None) => 1,
+        }
+        .max(1)
+    }
+
+    fn is_gif(&self) -> bool {
+        self.output
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("gif"))
+            .unwrap_or(false)
+    }
+}
+
+pub fn render_to_files(options: &RenderOptions) -> Result<()> {
+    let f = std::fs::read(&options.file)?;
+    let mut render = ScreenRender::new_from_file(&f)?;
+    options.overrides.apply(&mut render);
+    render.setup_monitor()?;
+
+    let frame_count = options.frame_count(render.fps);
+    let frame_duration = (1000. / render.fps) as u128;
+    let _ = monitor::set_update_delay(frame_duration);
+    info!("无头渲染:{} 帧数:{frame_count} 帧时间:{frame_duration}ms", options.file);
+
+    let mut frames = Vec::with_capacity(frame_count as usize);
+    let mut last_draw_time: Option<Instant> = None;
+    while frames.len() < frame_count as usize {
+        // 和主循环一样按照帧率渲染，监控数据和GIF动画才能正常更新
+        if let Some(t) = last_draw_time.as_ref() {
+            if t.elapsed().as_millis() < frame_duration {
+                std::thread::sleep(Duration::from_millis(5));
+                continue;
+            }
+        }
+        last_draw_time = Some(Instant::now());
+        render.render();
+        frames.push(render.rotated_frame());
+    }
+
+    if options.is_gif() {
+        write_gif(&frames, frame_duration as u32, &options.output)?;
+    } else {
+        write_png_sequence(&frames, &options.output)?;
+    }
+    eprintln!("已输出 {} 帧到: {}", frames.len(), options.output.display());
+    Ok(())
+}
+
+fn write_png_sequence(frames: &[RgbImage], dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    for (idx, frame) in frames.iter().enumerate() {
+        frame.save(dir.join(format!("frame_{idx:04}.png")))?;
+    }
+    Ok(())
+}
+
+fn write_gif(frames: &[RgbImage], delay_ms: u32, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    let mut encoder = GifEncoder::new(File::create(path)?);
+    encoder.set_repeat(Repeat::Infinite)?;
+    for frame in frames {
+        let rgba = image::DynamicImage::ImageRgb8(frame.clone()).to_rgba8();
+        encoder.encode_frame(Frame::from_parts(
+            rgba,
+            0,
+            0,
+            Delay::from_numer_denom_ms(delay_ms, 1),
+        ))?;
+    }
+    Ok(())
+}
@@ -1,24 +1,34 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::{path::Path, process::Command, time::{Duration, Instant}};
+use std::{path::Path, process::Command};
 
 use anyhow::{anyhow, Result};
-use image::{buffer::ConvertBuffer, RgbImage};
 use log::{error, info};
 #[cfg(feature = "tray")]
 use tao::event_loop::ControlFlow;
 
-use usb_screen::find_and_open_a_screen;
+use clap::Parser;
 
-use crate::screen::ScreenRender;
+use crate::{
+    cli::{Cli, Commands, ScreenOverrides},
+    screen::ScreenRender,
+};
+mod cli;
+mod control_api;
+mod data_source;
+mod dirty_rect;
 #[cfg(feature = "editor")]
 mod editor;
+mod headless;
 mod monitor;
 mod nmc;
 mod rgb565;
+mod runner;
+mod schedule;
 mod screen;
 mod usb_screen;
 mod wifi_screen;
+mod wifi_security;
 mod utils;
 mod widgets;
 mod offscreen_canvas;
@@ -33,6 +43,9 @@ fn main() -> Result<()> {
         .filter_level(log::LevelFilter::Info)
         .try_init();
 
+    // 先解析命令行, --help/--version 直接退出
+    let cli = Cli::parse();
+
     // 打印启动信息
     eprintln!("========================================");
     eprintln!("USB-Screen 启动");
@@ -82,201 +95,161 @@ fn main() -> Result<()> {
         }
     }
 
-    let args: Vec<String> = std::env::args().skip(1).collect();
-    eprintln!("命令行参数: {:?}", args);
-
-    let screen_file = match args.len() {
-        0 => read_screen_file(),
-        1 => Some(args[0].to_string()),
-        _ => None,
+    let command = match (cli.command, cli.file) {
+        (Some(command), _) => command,
+        // 兼容旧用法: USB-Screen [screen文件]
+        (None, file) => Commands::Run { files: file.into_iter().collect(), overrides: ScreenOverrides::default(), http: None },
     };
 
-    info!("screen_file={:?}", screen_file);
-
-    // 没有 .screen 文件时的处理
-    if screen_file.is_none() {
-        #[cfg(feature = "editor")]
-        {
-            // 启用 editor 时直接打开编辑器
-            eprintln!("未找到 .screen 文件, 启动编辑器...");
-            info!("editor start!");
-            editor::run()?;
+    match command {
+        Commands::Render(options) => {
+            let ret = headless::render_to_files(&options);
             monitor::clean();
-            return Ok(());
+            ret
         }
-        
-        #[cfg(not(feature = "editor"))]
-        {
-            eprintln!("错误: 未找到 .screen 文件!");
-            eprintln!("用法: USB-Screen <screen文件路径>");
-            eprintln!("      或在当前目录放置 .screen 文件");
-            return Ok(());
+        Commands::ListDevices => {
+            list_devices();
+            Ok(())
         }
-    }
+        Commands::Inspect { file, json } => inspect_screen_file(&file, json),
+        Commands::Editor => run_editor(),
+        Commands::Run { files, overrides, http } => {
+            let screen_files = if !files.is_empty() {
+                files
+            } else {
+                read_screen_file().into_iter().collect()
+            };
+            info!("screen_files={:?}", screen_files);
+            if !screen_files.is_empty() {
+                return create_tray_icon(screen_files, overrides, http);
+            }
 
-    if let Some(file) = screen_file {
-        #[cfg(feature = "editor")]
-        if file != "editor"{
-            create_tray_icon(file)?;
-            return Ok(());
-        }
+            // 没有 .screen 文件时的处理
+            #[cfg(feature = "editor")]
+            {
+                // 启用 editor 时直接打开编辑器
+                eprintln!("未找到 .screen 文件, 启动编辑器...");
+                run_editor()
+            }
 
-        #[cfg(not(feature = "editor"))]
-        create_tray_icon(file)?;
+            #[cfg(not(feature = "editor"))]
+            {
+                eprintln!("错误: 未找到 .screen 文件!");
+                eprintln!("用法: USB-Screen run <screen文件路径>");
+                eprintln!("      或在当前目录放置 .screen 文件");
+                Ok(())
+            }
+        }
     }
+}
 
+fn run_editor() -> Result<()> {
     #[cfg(feature = "editor")]
     {
         info!("editor start!");
         editor::run()?;
         monitor::clean();
+        Ok(())
     }
-    Ok(())
-}
 
-fn open_usb_screen(file: String) -> Result<()>{
-    eprintln!("----------------------------------------");
-    eprintln!("正在打开屏幕文件: {}", file);
-    info!("打开屏幕文件:{file}");
-    
-    // 检查文件是否存在
-    let file_path = Path::new(&file);
-    if !file_path.exists() {
-        eprintln!("错误: 文件不存在: {}", file);
-        return Err(anyhow!("文件不存在: {}", file));
+    #[cfg(not(feature = "editor"))]
+    {
+        Err(anyhow!("未启用 editor 功能，无法打开编辑器"))
     }
-    
-    // 读取文件
-    let f = match std::fs::read(&file) {
-        Ok(data) => {
-            eprintln!("文件读取成功, 大小: {} 字节", data.len());
-            data
-        }
-        Err(e) => {
-            eprintln!("错误: 读取文件失败: {}", e);
-            return Err(e.into());
-        }
-    };
-    
-    // 解析screen文件
-    let mut render = match ScreenRender::new_from_file(&f) {
-        Ok(r) => {
-            eprintln!("screen文件解析成功");
-            eprintln!("  屏幕尺寸: {}x{}", r.width, r.height);
-            eprintln!("  帧率: {} fps", r.fps);
-            eprintln!("  旋转角度: {} 度", r.rotate_degree);
-            if let Some(ip) = &r.device_ip {
-                eprintln!("  设备IP: {}", ip);
-            }
-            r
-        }
-        Err(e) => {
-            eprintln!("错误: 解析screen文件失败: {}", e);
-            return Err(e);
-        }
-    };
+}
 
-    // 设置监控
-    if let Err(e) = render.setup_monitor() {
-        eprintln!("警告: 设置监控失败: {}", e);
-    } else {
-        eprintln!("监控设置成功");
+fn list_devices() {
+    let devices = usb_screen::find_all_device();
+    // 局域网中的WiFi屏幕
+    let wifi_screens = wifi_screen::discover(wifi_screen::DISCOVERY_TIMEOUT).unwrap_or_else(|err| {
+        eprintln!("查找WiFi屏幕失败: {err}");
+        vec![]
+    });
+    if devices.is_empty() && wifi_screens.is_empty() {
+        eprintln!("未找到屏幕设备");
     }
-    
-    let mut usb_screen = None;
-
-    if let Some(ip) = render.device_ip.as_ref(){
-        eprintln!("使用WiFi屏幕模式, IP: {}", ip);
-        info!("设置了ip地址，使用wifi屏幕..");
-    }else {
-        eprintln!("使用USB屏幕模式, 正在查找USB设备...");
-        info!("未设置ip地址，使用 USB屏幕...");
-        usb_screen = usb_screen::find_and_open_a_screen();
-        if usb_screen.is_some() {
-            eprintln!("USB屏幕设备已找到并打开");
-        } else {
-            eprintln!("警告: 未找到USB屏幕设备, 将在主循环中重试");
-        }
+    // 每行一个设备: 名称 地址 尺寸 是否ESP32，使用制表符分隔方便脚本处理
+    for dev in devices {
+        println!("{}\t{}\t{}x{}\t{}", dev.label, dev.address, dev.width, dev.height, if dev.is_esp32_wifi { "esp32" } else { "-" });
+    }
+    // WiFi屏幕: 设备ID IP地址 尺寸 wifi
+    for screen in wifi_screens {
+        println!("{}\t{}\t{}x{}\twifi", screen.id, screen.ip, screen.width, screen.height);
     }
+}
 
-    info!("USB Screen是否已打开: {}", usb_screen.is_some());
-    eprintln!("进入主循环...");
-    let mut last_draw_time = Instant::now();
-    let frame_duration = (1000./render.fps) as u128;
-    info!("帧时间:{}ms", frame_duration);
-    //设置系统信息更新延迟
-    let _ = monitor::set_update_delay(frame_duration);
-    loop {
-        if last_draw_time.elapsed().as_millis() < frame_duration{
-            std::thread::sleep(Duration::from_millis(5));
-            continue;
+fn inspect_screen_file(file: &str, json: bool) -> Result<()> {
+    let f = std::fs::read(file)?;
+    let mut render = ScreenRender::new_from_file(&f)?;
+    if json {
+        let widgets: Vec<serde_json::Value> = render.widgets.iter().map(|w| {
+            let p = w.position();
+            serde_json::json!({
+                "id": w.id(),
+                "type_name": w.type_name(),
+                "label": w.get_label(),
+                "position": [p.left, p.top, p.right, p.bottom],
+            })
+        }).collect();
+        let info = serde_json::json!({
+            "width": render.width,
+            "height": render.height,
+            "model": render.model,
+            "fps": render.fps,
+            "rotate_degree": render.rotate_degree,
+            "device_address": render.device_address,
+            "device_ip": render.device_ip,
+            "device_id": render.device_id,
+            "brightness": render.brightness,
+            "schedule": render.schedule,
+            "scaling": render.scaling,
+            "wifi_codec": render.wifi_codec,
+            "font_name": render.font_name,
+            "widgets": widgets,
+        });
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+    println!("屏幕尺寸: {}x{}", render.width, render.height);
+    println!("型号: {}", render.model);
+    println!("帧率: {} fps", render.fps);
+    println!("旋转角度: {} 度", render.rotate_degree);
+    println!("设备地址: {}", render.device_address.as_deref().unwrap_or("-"));
+    println!("设备IP: {}", render.device_ip.as_deref().unwrap_or("-"));
+    println!("设备ID: {}", render.device_id.as_deref().unwrap_or("-"));
+    println!("亮度: {}", render.brightness.map(|b| b.to_string()).unwrap_or("-".to_string()));
+    if let Some(schedule) = render.schedule.as_ref() {
+        if let (Some(start), Some(end)) = (schedule.quiet_start.as_ref(), schedule.quiet_end.as_ref()) {
+            println!("安静时段: {start}-{end}");
         }
-        last_draw_time = Instant::now();
-        render.render();
-        let frame: RgbImage = render.canvas.image_data().convert();
-        //旋转
-        let frame = if render.rotate_degree == 90 {
-            image::imageops::rotate90(&frame)
-        }else if render.rotate_degree == 180{
-            image::imageops::rotate180(&frame)
-        }else if render.rotate_degree == 270{
-            image::imageops::rotate270(&frame)
-        }else{
-            frame
-        };
-        // let rgb565 = rgb888_to_rgb565_u16(&frame, frame.width() as usize, frame.height() as usize);
-        if let Some(ip) = render.device_ip.as_ref(){
-            //连接wifi屏幕
-            if let Ok(wifi_scr_status) = wifi_screen::get_status(){
-                match wifi_scr_status.status{
-                    wifi_screen::Status::NotConnected | wifi_screen::Status::ConnectFail
-                    | wifi_screen::Status::Disconnected => {
-                        std::thread::sleep(Duration::from_secs(2));
-                        let _ = wifi_screen::send_message(wifi_screen::Message::Connect(ip.to_string()));
-                    }
-                    wifi_screen::Status::Connected => {
-                        // 使用 try_send 避免阻塞，如果上一帧还在发送中则跳过当前帧
-                        // 这样可以始终发送最新帧，提高响应速度
-                        let _ = wifi_screen::try_send_message(wifi_screen::Message::Image(frame.convert()));
-                    }
-                    wifi_screen::Status::Connecting => {
-                        
-                    }
-                }
-            }
-        }else{
-            if usb_screen.is_none() {
-                std::thread::sleep(Duration::from_millis(2000));
-                info!("open USB Screen...");
-                usb_screen = find_and_open_a_screen();
-            } else {
-                let screen = usb_screen.as_mut().unwrap();
-                if let Err(err) = screen.draw_rgb_image(
-                    0,
-                    0,
-                    &frame
-                )
-                {
-                    error!("屏幕绘制失败:{err:?}");
-                    usb_screen = None;
-                }
-            }
+        if let Some(idle_minutes) = schedule.idle_minutes {
+            println!("空闲休眠: {idle_minutes}分钟");
         }
     }
+    let [r, g, b] = render.scaling.background;
+    println!("WiFi屏幕缩放: {:?} 背景色: #{r:02X}{g:02X}{b:02X}", render.scaling.mode);
+    println!("WiFi屏幕编码: {:?}", render.wifi_codec);
+    println!("字体: {}", render.font_name);
+    println!("组件数量: {}", render.widgets.len());
+    for w in render.widgets.iter_mut() {
+        let p = w.position();
+        println!("  {}\t{}\t{}\t({},{})-({},{})", w.id(), w.type_name(), w.get_label(), p.left, p.top, p.right, p.bottom);
+    }
+    Ok(())
 }
 
 #[allow(unreachable_code)]
-fn create_tray_icon(file: String) -> Result<()> {
+fn create_tray_icon(files: Vec<String>, overrides: ScreenOverrides, http: Option<String>) -> Result<()> {
     eprintln!("========================================");
-    eprintln!("create_tray_icon 被调用, 文件: {}", file);
+    eprintln!("create_tray_icon 被调用, 文件: {:?}", files);
 
-    #[cfg(not(feature = "editor"))]
+    #[cfg(not(feature = "tray"))]
     {
-        eprintln!("无editor模式, 直接运行屏幕显示...");
-        let ret = open_usb_screen(file);
+        eprintln!("无托盘模式, 直接运行屏幕显示...");
+        let ret = runner::run_screen_files(files, overrides, http);
         match &ret {
-            Ok(_) => eprintln!("open_usb_screen 正常退出"),
-            Err(e) => eprintln!("open_usb_screen 错误退出: {}", e),
+            Ok(_) => eprintln!("run_screen_files 正常退出"),
+            Err(e) => eprintln!("run_screen_files 错误退出: {}", e),
         }
         error!("{:?}", ret);
         return Ok(());
@@ -285,7 +258,7 @@ fn create_tray_icon(file: String) -> Result<()> {
     #[cfg(feature = "tray")]
     {
         std::thread::spawn(move ||{
-            let ret = open_usb_screen(file);
+            let ret = runner::run_screen_files(files, overrides, http);
             error!("{:?}", ret);
         });
     
@@ -12,7 +12,7 @@ use rust_ephemeris::lunnar::SolorDate;
 use serde::{Deserialize, Serialize};
 
 use std::{
-    collections::HashMap, sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard}, time::{Duration, Instant, SystemTime}
+    collections::{HashMap, VecDeque}, sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard}, time::{Duration, Instant, SystemTime}
 };
 use sysinfo::Networks;
 
@@ -21,6 +21,14 @@ use crate::nmc::{query_weather, City, RealWeather};
 const UPDATE_WEATHER_DELAY: u128 = 1000 * 60 * 5;
 const UPDATE_NET_IP_DELAY: u128 = 1000 * 60 * 5;
 pub const EMPTY_STRING: &str = "N/A";
+// 历史数据每秒采样一次，保留1小时
+const HISTORY_INTERVAL: u128 = 1000;
+const HISTORY_CAPACITY: usize = 3600;
+
+// 记录历史数据的指标，名称和数据源相同，见 SystemInfo::record_history
+// 百分比: cpu_usage, memory_percent, swap_percent
+// 字节/秒: received_speed, transmitted_speed, disk_read_speed, disk_write_speed
+// 摄氏度: cpu_temp., gpu_temp.
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SystemUptime {
@@ -63,6 +71,62 @@ pub struct HardwareData {
     pub gpu_infos: Vec<HardwareInfo>,
 }
 
+// 监控数值的单位，由组件按照自己的格式选项格式化
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    None,
+    Percent,
+    Celsius,
+    Watt,
+    Rpm,
+    MHz,
+    Bytes,
+    BytesPerSec,
+}
+
+// 带单位的监控数值
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub value: f64,
+    pub unit: Unit,
+    // 满量程(内存总量、磁盘容量), 用于换算百分比
+    pub total: Option<f64>,
+    // 附加说明, 例如磁盘的挂载点
+    pub label: Option<String>,
+}
+
+impl Metric {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Self { value, unit, total: None, label: None }
+    }
+
+    pub fn with_total(mut self, total: f64) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    pub fn with_label(mut self, label: String) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    // 百分比: 有满量程时按照满量程换算，否则直接使用数值(百分比、温度)
+    pub fn percent(&self) -> f64 {
+        match self.total {
+            Some(total) if total > 0. => self.value / total * 100.,
+            Some(_) => 0.,
+            None => self.value,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DiskUsage {
+    mount_point: String,
+    used: u64,
+    total: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebcamInfo{
     pub index: u32,
@@ -73,6 +137,9 @@ pub struct WebcamInfo{
 
 pub struct SystemInfo {
     update_delay: u128,
+    // 运行中的屏幕数量和休眠的屏幕数量，所有屏幕都休眠时暂停采集
+    screens: usize,
+    sleeping_screens: usize,
     watch_memory: bool,
     watch_disk: bool,
     watch_disk_speed: bool,
@@ -90,14 +157,13 @@ pub struct SystemInfo {
     watch_network_speed: bool,
     watch_net_ip: bool,
 
-    memory_info: String,
-    memory_percent: String,
-    swap_info: String,
-    swap_percent: String,
-    num_cpus: String,
+    // (已用, 总量) 字节
+    memory: Option<(u64, u64)>,
+    swap: Option<(u64, u64)>,
+    num_cpus: Option<usize>,
     cpu_brand: String,
-    cpu_usage_percpu: HashMap<usize, String>,
-    cpu_usage: String,
+    cpu_usage_percpu: HashMap<usize, f32>,
+    cpu_usage: Option<f32>,
     cpu_clock_speed: Vec<f32>,
     cpu_temperatures: Vec<f32>,
     cpu_temperature_total: f32,
@@ -114,10 +180,12 @@ pub struct SystemInfo {
     gpu_memory_load: Vec<f32>,
     gpu_memory_total: Vec<f32>,
     gpu_load_total: Vec<f32>,
-    num_process: String,
-    disk_usage: HashMap<usize, String>,
-    disk_speed_per_sec: (String, String),
-    network_speed_per_sec: (String, String),
+    num_process: Option<usize>,
+    disk_usage: HashMap<usize, DiskUsage>,
+    // (读取, 写入) 字节/秒
+    disk_speed_per_sec: Option<(f64, f64)>,
+    // (接收, 发送) 字节/秒
+    network_speed_per_sec: Option<(f64, f64)>,
     system_name: String,
     kernel_version: String,
     os_version: String,
@@ -132,13 +200,17 @@ pub struct SystemInfo {
     //缓存最新的相机图像
     webcam_frame: Option<RgbImage>,
     //监控的相机编号以及帧率
-    webcam_info: Option<WebcamInfo>
+    webcam_info: Option<WebcamInfo>,
+    // 历史数据 (时间戳毫秒, 数值)
+    history: HashMap<&'static str, VecDeque<(u128, f64)>>,
 }
 
 impl SystemInfo {
     pub fn new() -> Self {
         Self {
             update_delay: 1000,
+            screens: 1,
+            sleeping_screens: 0,
             watch_memory: false,
             watch_disk: false,
             watch_cpu: false,
@@ -155,12 +227,12 @@ impl SystemInfo {
             watch_network_speed: false,
             watch_net_ip: false,
 
-            memory_info: EMPTY_STRING.to_string(),
-            swap_info: EMPTY_STRING.to_string(),
-            num_cpus: EMPTY_STRING.to_string(),
+            memory: None,
+            swap: None,
+            num_cpus: None,
             cpu_brand: EMPTY_STRING.to_string(),
             cpu_usage_percpu: HashMap::new(),
-            cpu_usage: EMPTY_STRING.to_string(),
+            cpu_usage: None,
             cpu_clock_speed: vec![],
             cpu_temperatures: vec![],
             cpu_temperature_total: 0.,
@@ -177,7 +249,7 @@ impl SystemInfo {
             gpu_cores_power: 0.,
             gpu_package_power: 0.,
             gpu_temperature_total: vec![],
-            num_process: EMPTY_STRING.to_string(),
+            num_process: None,
             disk_usage: HashMap::new(),
             system_name: EMPTY_STRING.to_string(),
             kernel_version: sysinfo::System::kernel_version().unwrap_or(String::from("N/A")),
@@ -186,17 +258,69 @@ impl SystemInfo {
             watch_weather: None,
             weather_info: None,
             cpu_freq_query_task: None,
-            disk_speed_per_sec: (EMPTY_STRING.to_string(), EMPTY_STRING.to_string()),
+            disk_speed_per_sec: None,
             watch_disk_speed_task: None,
             watch_network_speed_task: None,
-            network_speed_per_sec: (EMPTY_STRING.to_string(), EMPTY_STRING.to_string()),
-            memory_percent: EMPTY_STRING.to_string(),
-            swap_percent: EMPTY_STRING.to_string(),
+            network_speed_per_sec: None,
             local_ip: EMPTY_STRING.to_string(),
             net_ip: None,
             webcam_frame: None,
             webcam_info: None,
             watch_webcam_task: None,
+            history: HashMap::new(),
+        }
+    }
+
+    fn cpu_temperature_value(&self) -> Option<f32> {
+        if self.cpu_temperature_total > 0.0 {
+            Some(self.cpu_temperature_total)
+        } else {
+            self.cpu_temperatures
+                .iter()
+                .copied()
+                .filter(|value| *value > 0.0)
+                .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
+        }
+    }
+
+    fn gpu_temperature_value(&self, index: usize) -> Option<f32> {
+        let list_temperature = pick_index_or_first(&self.gpu_temperatures, index)
+            .and_then(|temps| {
+                temps.iter()
+                    .copied()
+                    .filter(|value| *value > 0.0)
+                    .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
+            });
+        let total_temperature = pick_index_or_first(&self.gpu_temperature_total, index)
+            .copied()
+            .filter(|value| *value > 0.0);
+        total_temperature.or(list_temperature)
+    }
+
+    // 采样当前的指标，没有数据的指标不记录
+    fn record_history(&mut self, time: u128) {
+        let percent = |(used, total): (u64, u64)| {
+            if total > 0 { used as f64 / total as f64 * 100. } else { 0. }
+        };
+        let mut samples = vec![];
+        samples.push(("cpu_usage", self.cpu_usage.map(|v| v as f64)));
+        samples.push(("memory_percent", self.memory.map(percent)));
+        samples.push(("swap_percent", self.swap.map(percent)));
+        samples.push(("received_speed", self.network_speed_per_sec.map(|v| v.0)));
+        samples.push(("transmitted_speed", self.network_speed_per_sec.map(|v| v.1)));
+        samples.push(("disk_read_speed", self.disk_speed_per_sec.map(|v| v.0)));
+        samples.push(("disk_write_speed", self.disk_speed_per_sec.map(|v| v.1)));
+        samples.push(("cpu_temp.", self.cpu_temperature_value().map(|v| v as f64)));
+        samples.push(("gpu_temp.", self.gpu_temperature_value(0).map(|v| v as f64)));
+
+        for (name, value) in samples {
+            if let Some(value) = value {
+                let history = self.history.entry(name).or_default();
+                if history.len() >= HISTORY_CAPACITY {
+                    history.pop_front();
+                }
+                history.push_back((time, value));
+            }
         }
     }
 }
@@ -230,6 +354,7 @@ fn start_refresh_task(ctx: Arc<RwLock<SystemInfo>>) {
 
         let mut last_update_time = 0;
         let mut last_update_net_ip_time = 0;
+        let mut last_history_time = 0;
 
         //(city, time)
         let last_weather_update_store: Arc<Mutex<(Option<City>, u128)>> =
@@ -238,11 +363,16 @@ fn start_refresh_task(ctx: Arc<RwLock<SystemInfo>>) {
         loop {
             let current_time = current_timestamp();
 
-            let update_delay = match ctx.read() {
-                Err(_err) => 1000,
-                Ok(ctx) => ctx.update_delay,
+            let (update_delay, paused) = match ctx.read() {
+                Err(_err) => (1000, false),
+                Ok(ctx) => (ctx.update_delay, ctx.sleeping_screens >= ctx.screens),
             };
 
+            if paused {
+                std::thread::sleep(Duration::from_millis(500));
+                continue;
+            }
+
             //相机根据帧率刷新
             let watch_webcam = match ctx.read() {
                 Err(_err) => return,
@@ -362,43 +492,22 @@ fn start_refresh_task(ctx: Arc<RwLock<SystemInfo>>) {
                     let cpu_usage = sysinfo_system.global_cpu_usage();
 
                     try_write(|mut ctx| {
-                        ctx.num_cpus = format!("{}", cpus.len());
+                        ctx.num_cpus = Some(cpus.len());
                         ctx.cpu_brand = match cpus.get(0) {
                             Some(cpu) => cpu.brand().to_string().trim().to_string(),
                             None => EMPTY_STRING.to_string(),
                         };
                         for (cpu_idx, cpu) in cpus.iter().enumerate() {
-                            ctx.cpu_usage_percpu
-                                .insert(cpu_idx, format!("{:.1}%", cpu.cpu_usage()));
+                            ctx.cpu_usage_percpu.insert(cpu_idx, cpu.cpu_usage());
                         }
-                        ctx.cpu_usage = format!("{:.1}%", cpu_usage);
+                        ctx.cpu_usage = Some(cpu_usage);
                     });
                 }
                 if watch_memory {
                     sysinfo_system.refresh_memory();
                     try_write(|mut ctx| {
-                        ctx.memory_info = format!(
-                            "{}/{}GB",
-                            bytes_to_gb(sysinfo_system.used_memory()),
-                            bytes_to_gb(sysinfo_system.total_memory())
-                        );
-                        ctx.swap_info = format!(
-                            "{}/{}GB",
-                            bytes_to_gb(sysinfo_system.used_swap()),
-                            bytes_to_gb(sysinfo_system.total_swap())
-                        );
-                        ctx.memory_percent = format!(
-                            "{}%",
-                            ((sysinfo_system.used_memory() as f64
-                                / sysinfo_system.total_memory() as f64)
-                                * 100.) as usize
-                        );
-                        ctx.swap_percent = format!(
-                            "{}%",
-                            ((sysinfo_system.used_swap() as f64
-                                / sysinfo_system.total_swap() as f64)
-                                * 100.) as usize
-                        );
+                        ctx.memory = Some((sysinfo_system.used_memory(), sysinfo_system.total_memory()));
+                        ctx.swap = Some((sysinfo_system.used_swap(), sysinfo_system.total_swap()));
                     });
                 }
                 if watch_disk {
@@ -408,12 +517,11 @@ fn start_refresh_task(ctx: Arc<RwLock<SystemInfo>>) {
                             let path = disk.mount_point().to_str().unwrap_or("").replace("\\", "");
                             ctx.disk_usage.insert(
                                 disk_idx,
-                                format!(
-                                    "({}) {}/{}GB",
-                                    path,
-                                    bytes_to_gb(disk.total_space() - disk.available_space()),
-                                    bytes_to_gb(disk.total_space())
-                                ),
+                                DiskUsage {
+                                    mount_point: path,
+                                    used: disk.total_space() - disk.available_space(),
+                                    total: disk.total_space(),
+                                },
                             );
                         }
                     });
@@ -450,7 +558,7 @@ fn start_refresh_task(ctx: Arc<RwLock<SystemInfo>>) {
                 if watch_process {
                     sysinfo_system.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
                     try_write(|mut ctx| {
-                        ctx.num_process = format!("{}", sysinfo_system.processes().keys().len());
+                        ctx.num_process = Some(sysinfo_system.processes().keys().len());
                     });
                 }
 
@@ -496,6 +604,14 @@ fn start_refresh_task(ctx: Arc<RwLock<SystemInfo>>) {
                 }
             }
 
+            //历史数据按照固定间隔采样，和刷新频率无关
+            if current_time - last_history_time >= HISTORY_INTERVAL {
+                last_history_time = current_time;
+                if let Ok(mut ctx) = ctx.write() {
+                    ctx.record_history(current_time);
+                }
+            }
+
             std::thread::sleep(Duration::from_millis(10));
         }
     });
@@ -509,12 +625,6 @@ fn current_timestamp() -> u128 {
     since_the_epoch.as_millis()
 }
 
-pub fn bytes_to_gb(bytes: u64) -> String {
-    let kb = (bytes / 1024) as f64;
-    let gb = kb / 1024. / 1024.;
-    format!("{:.1}", gb)
-}
-
 fn try_read_ctx<'a>() -> Option<RwLockReadGuard<'a, SystemInfo>> {
     match SYSTEM_INFO.try_read() {
         Ok(sys) => Some(sys),
@@ -529,18 +639,35 @@ pub fn set_update_delay(update_delay: u128) -> Result<()> {
     Ok(())
 }
 
+// 设置同时运行的屏幕数量
+pub fn set_screen_count(screens: usize) -> Result<()> {
+    let mut sys_info = SYSTEM_INFO.write().map_err(|err| anyhow!("{:?}", err))?;
+    sys_info.screens = screens.max(1);
+    Ok(())
+}
+
+// 屏幕进入或退出休眠，所有屏幕都休眠时暂停采集系统信息
+pub fn set_screen_sleeping(sleeping: bool) -> Result<()> {
+    let mut sys_info = SYSTEM_INFO.write().map_err(|err| anyhow!("{:?}", err))?;
+    if sleeping {
+        sys_info.sleeping_screens += 1;
+    } else {
+        sys_info.sleeping_screens = sys_info.sleeping_screens.saturating_sub(1);
+    }
+    info!("休眠的屏幕:{}/{}", sys_info.sleeping_screens, sys_info.screens);
+    Ok(())
+}
+
 pub fn watch_cpu(watch_cpu: bool) -> Result<()> {
     let mut sys_info = SYSTEM_INFO.write().map_err(|err| anyhow!("{:?}", err))?;
     sys_info.watch_cpu = watch_cpu;
     Ok(())
 }
 
-#[cfg(windows)]
 fn pick_index_or_first<T>(items: &[T], index: usize) -> Option<&T> {
     items.get(index).or_else(|| items.first())
 }
 
-#[cfg(windows)]
 fn pick_map_value_or_first<T: Clone>(items: &HashMap<usize, T>, index: usize) -> Option<T> {
     items.get(&index)
         .cloned()
@@ -687,91 +814,84 @@ pub fn watch_webcam(webcam_info: Option<WebcamInfo>) -> Result<()> {
     Ok(())
 }
 
-pub fn num_cpus() -> Option<String> {
-    Some(try_read_ctx()?.num_cpus.clone())
+pub fn num_cpus() -> Option<Metric> {
+    let num_cpus = try_read_ctx()?.num_cpus?;
+    Some(Metric::new(num_cpus as f64, Unit::None))
 }
 
 pub fn cpu_brand() -> Option<String> {
     Some(try_read_ctx()?.cpu_brand.clone())
 }
 
-pub fn memory_info() -> Option<String> {
-    Some(try_read_ctx()?.memory_info.clone())
-}
-
-pub fn memory_total() -> Option<String> {
-    let info = try_read_ctx()?.memory_info.clone();
-    let info = if info.contains("/") {
-        info.split("/").last().unwrap().replace("GB", "G")
-    } else {
-        info
-    };
-    Some(info)
+// 已用内存，满量程为内存总量
+pub fn memory() -> Option<Metric> {
+    let (used, total) = try_read_ctx()?.memory?;
+    Some(Metric::new(used as f64, Unit::Bytes).with_total(total as f64))
 }
 
-pub fn memory_percent() -> Option<String> {
-    Some(try_read_ctx()?.memory_percent.clone())
+pub fn memory_total() -> Option<Metric> {
+    let (_used, total) = try_read_ctx()?.memory?;
+    Some(Metric::new(total as f64, Unit::Bytes))
 }
 
-pub fn swap_percent() -> Option<String> {
-    Some(try_read_ctx()?.swap_percent.clone())
+// 已用交换空间，满量程为交换空间总量
+pub fn swap() -> Option<Metric> {
+    let (used, total) = try_read_ctx()?.swap?;
+    Some(Metric::new(used as f64, Unit::Bytes).with_total(total as f64))
 }
 
-pub fn swap_info() -> Option<String> {
-    Some(try_read_ctx()?.swap_info.clone())
+pub fn cpu_usage_percpu(index: usize) -> Option<Metric> {
+    let usage = *try_read_ctx()?.cpu_usage_percpu.get(&index)?;
+    Some(Metric::new(usage as f64, Unit::Percent))
 }
 
-pub fn cpu_usage_percpu(index: usize) -> Option<String> {
-    try_read_ctx()?.cpu_usage_percpu.clone().remove(&index)
+// 所有核心的使用率，按照核心编号排列
+pub fn cpu_usage_cores() -> Vec<f32> {
+    let ctx = match try_read_ctx() {
+        Some(ctx) => ctx,
+        None => return vec![],
+    };
+    let mut cores: Vec<(usize, f32)> = ctx.cpu_usage_percpu.iter().map(|(idx, usage)| (*idx, *usage)).collect();
+    cores.sort_by_key(|(idx, _)| *idx);
+    cores.into_iter().map(|(_, usage)| usage).collect()
 }
 
-pub fn cpu_usage() -> Option<String> {
-    Some(try_read_ctx()?.cpu_usage.clone())
+pub fn cpu_usage() -> Option<Metric> {
+    let usage = try_read_ctx()?.cpu_usage?;
+    Some(Metric::new(usage as f64, Unit::Percent))
 }
 
 pub fn webcam_frame() -> Option<RgbImage> {
     try_read_ctx()?.webcam_frame.clone()
 }
 
-pub fn cpu_clock_speed(index: Option<usize>) -> Option<String> {
+pub fn cpu_clock_speed(index: Option<usize>) -> Option<Metric> {
     let cpu_clock_speed = try_read_ctx()?.cpu_clock_speed.clone();
-    match index {
-        Some(idx) => cpu_clock_speed
-            .get(idx)
-            .map(|v| format!("{:.2} GHz", v / 1000.)),
+    let mhz = match index {
+        Some(idx) => cpu_clock_speed.get(idx).copied(),
         None => cpu_clock_speed
             .into_iter()
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
-            .map(|v| format!("{:.2} GHz", v / 1000.)),
-    }
-}
-
-pub fn cpu_temperature() -> Option<String> {
-    let ctx = try_read_ctx()?;
-    let temperature = if ctx.cpu_temperature_total > 0.0 {
-        Some(ctx.cpu_temperature_total)
-    } else {
-        ctx.cpu_temperatures
-            .iter()
-            .copied()
-            .filter(|value| *value > 0.0)
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
+            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less)),
     }?;
+    Some(Metric::new(mhz as f64, Unit::MHz))
+}
 
-    Some(format!("{temperature:.1}°C"))
+pub fn cpu_temperature() -> Option<Metric> {
+    let temperature = try_read_ctx()?.cpu_temperature_value()?;
+    Some(Metric::new(temperature as f64, Unit::Celsius))
 }
 
-pub fn cpu_cores_power() -> Option<String> {
+pub fn cpu_cores_power() -> Option<Metric> {
     let ctx = try_read_ctx()?;
     let power = if ctx.cpu_cores_power > 0.0 {
         ctx.cpu_cores_power
     } else {
         ctx.cpu_package_power
     };
-    Some(format!("{:.1}W", power))
+    Some(Metric::new(power as f64, Unit::Watt))
 }
 
-pub fn cpu_package_power() -> Option<String> {
+pub fn cpu_package_power() -> Option<Metric> {
     let ctx = try_read_ctx()?;
     let power = if ctx.cpu_package_power > 0.0 {
         ctx.cpu_package_power
@@ -781,25 +901,25 @@ pub fn cpu_package_power() -> Option<String> {
     if power <= 0.0 {
         return None;
     }
-    Some(format!("{power:.1}W"))
+    Some(Metric::new(power as f64, Unit::Watt))
 }
 
-pub fn cpu_fan() -> Option<String> {
+pub fn cpu_fan() -> Option<Metric> {
     let ctx = try_read_ctx()?;
     if ctx.cpu_fans.len() == 0 {
         return None;
     }
-    Some(format!("{}RPM", ctx.cpu_fans[0]))
+    Some(Metric::new(ctx.cpu_fans[0] as f64, Unit::Rpm))
 }
 
-pub fn gpu_load(index: usize) -> Option<String> {
+pub fn gpu_load(index: usize) -> Option<Metric> {
     let ctx = try_read_ctx()?;
     let mut load_total = pick_index_or_first(&ctx.gpu_load_total, index);
 
     if load_total.is_none(){
         return pick_index_or_first(&ctx.gpu_load, index).map(|loads|{
             let load = loads.get(0).unwrap_or(&0.);
-            format!("{load:.1}%")
+            Metric::new(*load as f64, Unit::Percent)
         });
     }
 
@@ -810,33 +930,26 @@ pub fn gpu_load(index: usize) -> Option<String> {
             }
         }
     }
-    load_total.map(|load| format!("{load:.1}%"))
+    load_total.map(|load| Metric::new(*load as f64, Unit::Percent))
 }
 
-pub fn gpu_memory_load(index: usize) -> Option<String> {
+pub fn gpu_memory_load(index: usize) -> Option<Metric> {
     let ctx = try_read_ctx()?;
 
     return pick_index_or_first(&ctx.gpu_memory_load, index).map(|load|{
-        format!("{:.1}%", load)
-    });
-}
-
-pub fn gpu_memory_total_mb(index: usize) -> Option<String> {
-    let ctx = try_read_ctx()?;
-    return pick_index_or_first(&ctx.gpu_memory_total, index).map(|total|{
-        format!("{total:.1}")
+        Metric::new(*load as f64, Unit::Percent)
     });
 }
 
-pub fn gpu_memory_total_gb(index: usize) -> Option<String> {
+// 显存总量，硬件监控返回的单位为MB
+pub fn gpu_memory_total(index: usize) -> Option<Metric> {
     let ctx = try_read_ctx()?;
     return pick_index_or_first(&ctx.gpu_memory_total, index).map(|total|{
-        let gb = total / 1024.;
-        format!("{:.1}", gb)
+        Metric::new(*total as f64 * 1024. * 1024., Unit::Bytes)
     });
 }
 
-pub fn gpu_clocks(index: usize) -> Option<String> {
+pub fn gpu_clocks(index: usize) -> Option<Metric> {
     let gpu_clocks = try_read_ctx()?.gpu_clocks.clone();
     if gpu_clocks.len() == 0 {
         return None;
@@ -846,36 +959,25 @@ pub fn gpu_clocks(index: usize) -> Option<String> {
         .clone()
         .into_iter()
         .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
-        .map(|v| format!("{:.1} GHz", v / 1000.))
+        .map(|v| Metric::new(v as f64, Unit::MHz))
 }
 
-pub fn gpu_temperature(index: usize) -> Option<String> {
-    let ctx = try_read_ctx()?;
-    let list_temperature = pick_index_or_first(&ctx.gpu_temperatures, index)
-        .and_then(|temps| {
-            temps.iter()
-                .copied()
-                .filter(|value| *value > 0.0)
-                .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Less))
-        });
-    let total_temperature = pick_index_or_first(&ctx.gpu_temperature_total, index)
-        .copied()
-        .filter(|value| *value > 0.0);
-    let temperature = total_temperature.or(list_temperature)?;
-    Some(format!("{temperature:.1}°C"))
+pub fn gpu_temperature(index: usize) -> Option<Metric> {
+    let temperature = try_read_ctx()?.gpu_temperature_value(index)?;
+    Some(Metric::new(temperature as f64, Unit::Celsius))
 }
 
-pub fn gpu_cores_power() -> Option<String> {
+pub fn gpu_cores_power() -> Option<Metric> {
     let ctx = try_read_ctx()?;
     let power = if ctx.gpu_cores_power > 0.0 {
         ctx.gpu_cores_power
     } else {
         ctx.gpu_package_power
     };
-    Some(format!("{:.1}W", power))
+    Some(Metric::new(power as f64, Unit::Watt))
 }
 
-pub fn gpu_package_power() -> Option<String> {
+pub fn gpu_package_power() -> Option<Metric> {
     let ctx = try_read_ctx()?;
     let power = if ctx.gpu_package_power > 0.0 {
         ctx.gpu_package_power
@@ -885,32 +987,61 @@ pub fn gpu_package_power() -> Option<String> {
     if power <= 0.0 {
         return None;
     }
-    Some(format!("{power:.1}W"))
+    Some(Metric::new(power as f64, Unit::Watt))
 }
 
-pub fn gpu_fan(index: usize) -> Option<String> {
+pub fn gpu_fan(index: usize) -> Option<Metric> {
     let ctx = try_read_ctx()?;
     let fans = pick_index_or_first(&ctx.gpu_fans, index)?;
     if fans.len() == 0 {
         return None;
     }
-    Some(format!("{:.1}RPM", fans[0]))
+    Some(Metric::new(fans[0] as f64, Unit::Rpm))
+}
+
+pub fn num_process() -> Option<Metric> {
+    let num_process = try_read_ctx()?.num_process?;
+    Some(Metric::new(num_process as f64, Unit::None))
 }
 
-pub fn num_process() -> Option<String> {
-    Some(try_read_ctx()?.num_process.clone())
+// 磁盘已用空间，满量程为磁盘容量，挂载点作为附加说明
+pub fn disk_usage(index: usize) -> Option<Metric> {
+    let disk = pick_map_value_or_first(&try_read_ctx()?.disk_usage, index)?;
+    Some(
+        Metric::new(disk.used as f64, Unit::Bytes)
+            .with_total(disk.total as f64)
+            .with_label(disk.mount_point),
+    )
 }
 
-pub fn disk_usage(index: usize) -> Option<String> {
-    pick_map_value_or_first(&try_read_ctx()?.disk_usage, index)
+pub fn disk_speed_per_sec() -> Option<(Metric, Metric)> {
+    let (read, write) = try_read_ctx()?.disk_speed_per_sec?;
+    Some((Metric::new(read, Unit::BytesPerSec), Metric::new(write, Unit::BytesPerSec)))
 }
 
-pub fn disk_speed_per_sec() -> Option<(String, String)> {
-    Some(try_read_ctx()?.disk_speed_per_sec.clone())
+pub fn network_speed_per_sec() -> Option<(Metric, Metric)> {
+    let (received, transmitted) = try_read_ctx()?.network_speed_per_sec?;
+    Some((Metric::new(received, Unit::BytesPerSec), Metric::new(transmitted, Unit::BytesPerSec)))
 }
 
-pub fn network_speed_per_sec() -> Option<(String, String)> {
-    Some(try_read_ctx()?.network_speed_per_sec.clone())
+// 最近 window_secs 秒内的历史数据: (距离现在的秒数, 数值)，按时间顺序排列
+pub fn history(name: &str, window_secs: u32) -> Vec<(f64, f64)> {
+    // 采样和读取都很快，这里等待读锁，避免图表因为锁冲突闪烁
+    let ctx = match SYSTEM_INFO.read() {
+        Ok(ctx) => ctx,
+        Err(_) => return vec![],
+    };
+    let history = match ctx.history.get(name) {
+        Some(history) => history,
+        None => return vec![],
+    };
+    let now = current_timestamp();
+    let window = window_secs as u128 * 1000;
+    history
+        .iter()
+        .filter(|(time, _)| now.saturating_sub(*time) <= window)
+        .map(|(time, value)| (now.saturating_sub(*time) as f64 / 1000., *value))
+        .collect()
 }
 
 pub fn system_name() -> Option<String> {
@@ -1172,25 +1303,8 @@ pub fn start_network_counter_thread() -> std::thread::JoinHandle<()> {
                 }
             }
 
-            let received_kb = received as f64 / 1024.;
-            let transmitted_kb = transmitted as f64 / 1024.;
-            let received_mb = received as f64 / 1024. / 1024.;
-            let transmitted_mb = transmitted as f64 / 1024. / 1024.;
-
-            let (received_str, transmitted_str) = (
-                if received_mb >= 1. {
-                    format!("{:.1}MB/s", received_mb)
-                } else {
-                    format!("{:.1}KB/s", received_kb)
-                },
-                if transmitted_mb >= 1. {
-                    format!("{:.1}MB/s", transmitted_mb)
-                } else {
-                    format!("{:.1}KB/s", transmitted_kb)
-                },
-            );
             try_write(move |mut ctx| {
-                ctx.network_speed_per_sec = (received_str.to_owned(), transmitted_str.to_owned());
+                ctx.network_speed_per_sec = Some((received as f64, transmitted as f64));
             });
         }
     })
@@ -1450,10 +1564,8 @@ pub fn start_disk_counter_thread() -> std::thread::JoinHandle<()> {
                 //关闭PDH
                 PdhCloseQuery(query);
 
-                let read_str = format!("{:.1} MB/s", read_bytes_per_sec / 1024. / 1024.);
-                let write_str = format!("{:.1} MB/s", write_bytes_per_sec / 1024. / 1024.);
                 try_write(move |mut ctx| {
-                    ctx.disk_speed_per_sec = (read_str.to_owned(), write_str.to_owned());
+                    ctx.disk_speed_per_sec = Some((read_bytes_per_sec, write_bytes_per_sec));
                 });
             }
         }
@@ -1496,10 +1608,8 @@ pub fn start_disk_counter_thread() -> std::thread::JoinHandle<()> {
 
             prev_disk_io_counters = current_disk_io_counters;
 
-            let read_str = format!("{:.1} MB/s", counter.read_bytes() as f64 / 1024. / 1024.);
-            let write_str = format!("{:.1} MB/s", counter.write_bytes() as f64 / 1024. / 1024.);
             try_write(move |mut ctx| {
-                ctx.disk_speed_per_sec = (read_str.to_owned(), write_str.to_owned());
+                ctx.disk_speed_per_sec = Some((counter.read_bytes() as f64, counter.write_bytes() as f64));
             });
         }
     })
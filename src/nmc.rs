@@ -7,7 +7,7 @@ use once_cell::sync::Lazy;
 use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
 use serde::{Deserialize, Serialize};
 
-pub const CITIES: Lazy<Vec<City>> =
+pub static CITIES: Lazy<Vec<City>> =
     Lazy::new(|| serde_json::from_str(include_str!("../cities.json")).unwrap());
 
 pub const ICONS: Lazy<Vec<RgbaImage>> = Lazy::new(|| {
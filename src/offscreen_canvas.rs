@@ -5,7 +5,7 @@ use ab_glyph::{point, FontRef, GlyphId, OutlinedGlyph, PxScale, ScaleFont};
 use image::imageops::FilterType;
 use image::{Rgba, RgbaImage};
 use tiny_skia::{
-    Color, FillRule, Paint, PathBuilder, Pixmap, PixmapPaint, Stroke, Transform,
+    Color, FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, PixmapPaint, Stroke, Transform,
 };
 
 // 颜色常量
@@ -406,10 +406,67 @@ impl OffscreenCanvas {
             );
         }
     }
+
+    /// 绘制折线 (抗锯齿)
+    pub fn stroke_polyline(&mut self, points: &[(f32, f32)], stroke_width: f32, color: Rgba<u8>) {
+        let mut paint = Paint::default();
+        paint.set_color(rgba_to_color(color));
+        paint.anti_alias = true;
+
+        let stroke = Stroke {
+            width: stroke_width,
+            line_join: LineJoin::Round,
+            line_cap: LineCap::Round,
+            ..Default::default()
+        };
+
+        if let Some(path) = build_polyline(points, false) {
+            self.pixmap.stroke_path(
+                &path,
+                &paint,
+                &stroke,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+
+    /// 填充多边形 (抗锯齿)
+    pub fn fill_polygon(&mut self, points: &[(f32, f32)], color: Rgba<u8>) {
+        let mut paint = Paint::default();
+        paint.set_color(rgba_to_color(color));
+        paint.anti_alias = true;
+
+        if let Some(path) = build_polyline(points, true) {
+            self.pixmap.fill_path(
+                &path,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+    }
 }
 
 // ============ 辅助函数 ============
 
+/// 由顶点构建路径, 少于两个点时返回 None
+fn build_polyline(points: &[(f32, f32)], close: bool) -> Option<tiny_skia::Path> {
+    if points.len() < 2 {
+        return None;
+    }
+    let mut pb = PathBuilder::new();
+    pb.move_to(points[0].0, points[0].1);
+    for (x, y) in &points[1..] {
+        pb.line_to(*x, *y);
+    }
+    if close {
+        pb.close();
+    }
+    pb.finish()
+}
+
 /// Rgba 转换为 tiny-skia Color
 #[inline]
 fn rgba_to_color(rgba: Rgba<u8>) -> Color {
@@ -0,0 +1,574 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::Mutex, time::{Duration, Instant, SystemTime}};
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use image::{buffer::ConvertBuffer, RgbImage};
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+
+use crate::{
+    cli::ScreenOverrides,
+    control_api::{self, ScreenControl},
+    monitor,
+    schedule::{IdleTimer, SleepAction, SleepReason},
+    screen::ScreenRender,
+    usb_screen::{self, find_and_open_screen, DeviceWaiter, UsbScreenSender},
+    wifi_screen::{self, WifiScreen},
+};
+
+// WiFi屏幕断开后重新连接的间隔
+const WIFI_RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+// 每个screen文件绑定的设备: screen文件 -> (是否是WiFi屏幕, 设备地址/设备ID/IP)
+static DEVICE_BINDINGS: Lazy<Mutex<HashMap<String, (bool, String)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 每个屏幕的帧时间(screen文件 -> 毫秒)，系统信息按照帧率最高的屏幕刷新
+static FRAME_DURATIONS: Lazy<Mutex<HashMap<String, u128>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 记录屏幕的帧时间并更新系统信息的刷新间隔，启动和帧率改变时调用
+fn set_frame_duration(file: &str, duration: u128) {
+    if let Ok(mut durations) = FRAME_DURATIONS.lock() {
+        durations.insert(file.to_string(), duration);
+        if let Some(delay) = durations.values().min() {
+            let _ = monitor::set_update_delay(*delay);
+        }
+    }
+}
+
+// 运行一个或多个screen文件，每个screen文件绑定各自的设备(USB地址、串口名称或WiFi IP)
+// 所有屏幕共用同一个 monitor 刷新线程
+pub fn run_screen_files(files: Vec<String>, overrides: ScreenOverrides, http: Option<String>) -> Result<()> {
+    // 每个screen文件对应一个控制通道
+    let mut controls: Vec<Option<ScreenControl>> = match http.as_ref() {
+        Some(addr) => control_api::start(addr, &files)?.into_iter().map(Some).collect(),
+        None => files.iter().map(|_| None).collect(),
+    };
+
+    if files.len() == 1 {
+        let render = load_screen_file(&files[0], &overrides)?;
+        //设置系统信息更新延迟
+        set_frame_duration(&files[0], frame_duration(render.fps));
+        return run_screen(&files[0], &overrides, render, controls.remove(0));
+    }
+
+    if overrides.device_address.is_some() || overrides.device_ip.is_some() || overrides.device_id.is_some() {
+        return Err(anyhow!("打开多个screen文件时，请在每个screen文件中设置设备地址或IP，不能使用 --device-address/--device-ip/--device-id"));
+    }
+
+    let mut renders = vec![];
+    for file in &files {
+        renders.push(load_screen_file(file, &overrides)?);
+    }
+    check_device_bindings(&files, &renders)?;
+
+    // 为绑定了地址的屏幕预留设备，未绑定地址的屏幕不会打开这些设备
+    for address in renders.iter().filter_map(reserved_address) {
+        usb_screen::reserve_device_address(address);
+    }
+
+    // 所有屏幕都进入安静时段后才暂停系统信息采集
+    let _ = monitor::set_screen_count(renders.len());
+
+    // 系统信息按照帧率最高的屏幕刷新
+    for (file, render) in files.iter().zip(renders.iter()) {
+        set_frame_duration(file, frame_duration(render.fps));
+    }
+
+    let mut handles = vec![];
+    for (idx, (render, control)) in renders.into_iter().zip(controls).enumerate() {
+        let file = files[idx].clone();
+        let overrides = overrides.clone();
+        let handle = std::thread::Builder::new()
+            .name(format!("screen-{idx}"))
+            .spawn(move || {
+                let ret = run_screen(&file, &overrides, render, control);
+                error!("屏幕 {file} 退出: {ret:?}");
+            })?;
+        handles.push(handle);
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
+    Ok(())
+}
+
+// USB屏幕绑定的设备地址，需要预留给这个屏幕
+fn reserved_address(render: &ScreenRender) -> Option<&str> {
+    if render.is_wifi() {
+        None
+    } else {
+        render.device_address.as_deref()
+    }
+}
+
+// 检查多个屏幕的设备绑定是否冲突
+fn check_device_bindings(files: &[String], renders: &[ScreenRender]) -> Result<()> {
+    for (file, render) in files.iter().zip(renders) {
+        bind_device(file, render)?;
+    }
+    Ok(())
+}
+
+// 屏幕绑定的设备，WiFi屏幕设置了设备ID时按设备ID判断是否为同一个屏幕
+fn device_binding(render: &ScreenRender) -> Option<(bool, String)> {
+    let key = if render.is_wifi() {
+        render.device_id.as_ref().or(render.device_ip.as_ref())
+    } else {
+        render.device_address.as_ref()
+    };
+    key.map(|key| (render.is_wifi(), key.trim().to_lowercase()))
+}
+
+// 记录screen文件绑定的设备，和其他screen文件绑定了同一个设备时返回错误(重新加载时也会检查)
+fn bind_device(file: &str, render: &ScreenRender) -> Result<()> {
+    let mut bindings = DEVICE_BINDINGS.lock().map_err(|err| anyhow!("{err:?}"))?;
+    let binding = match device_binding(render) {
+        Some(binding) => binding,
+        None => {
+            bindings.remove(file);
+            return Ok(());
+        }
+    };
+    if let Some(other) = bindings.iter().find(|(f, b)| f.as_str() != file && **b == binding).map(|(f, _)| f) {
+        return Err(if binding.0 {
+            anyhow!("{other} 和 {file} 绑定了同一个WiFi屏幕: {}", binding.1)
+        } else {
+            anyhow!("{other} 和 {file} 绑定了同一个设备地址: {}", binding.1)
+        });
+    }
+    bindings.insert(file.to_string(), binding);
+    Ok(())
+}
+
+// 打开屏幕并启动发送线程
+fn open_usb_screen(device_address: Option<&str>) -> Option<UsbScreenSender> {
+    let screen = find_and_open_screen(device_address)?;
+    match UsbScreenSender::start(screen) {
+        Ok(sender) => Some(sender),
+        Err(err) => {
+            error!("启动屏幕发送线程失败:{err:?}");
+            None
+        }
+    }
+}
+
+// WiFi屏幕的连接消息: 绑定了设备ID时由上传线程通过局域网发现查找当前的地址
+fn connect_message(render: &ScreenRender) -> Option<wifi_screen::Message> {
+    match render.device_id.as_ref() {
+        Some(id) => Some(wifi_screen::Message::ConnectDevice(id.clone(), render.device_ip.clone())),
+        None => render.device_ip.clone().map(wifi_screen::Message::Connect),
+    }
+}
+
+// 屏幕的亮度和开关状态
+#[derive(Clone, Copy, PartialEq)]
+struct DisplayState {
+    brightness: Option<u8>,
+    display_on: bool,
+}
+
+impl DisplayState {
+    fn of(render: &ScreenRender, sleeping: Option<SleepReason>, usb_screen: Option<&UsbScreenSender>) -> Self {
+        // 休眠时按照计划关闭屏幕
+        let sleep_off = sleeping.is_some() && sleep_action(render, usb_screen) == SleepAction::Off;
+        Self { brightness: render.brightness, display_on: render.display_on && !sleep_off }
+    }
+}
+
+fn sleep_action(render: &ScreenRender, usb_screen: Option<&UsbScreenSender>) -> SleepAction {
+    let action = render.schedule.as_ref().map(|s| s.action).unwrap_or_default();
+    // USB屏幕没有声明支持开关命令时用黑屏代替
+    let power_off = render.is_wifi() || usb_screen.map(|s| s.info().supports(usb_screen::FEAT_POWER)).unwrap_or(false);
+    if action == SleepAction::Off && !power_off {
+        return SleepAction::Blank;
+    }
+    action
+}
+
+// 把亮度和开关状态中发生变化的部分发送到屏幕，applied 为空表示屏幕刚刚打开(状态未知)
+// 屏幕还没有打开时返回false，打开后再发送
+fn apply_display_state(render: &ScreenRender, state: DisplayState, usb_screen: Option<&UsbScreenSender>, wifi: Option<&WifiScreen>, applied: Option<DisplayState>) -> bool {
+    let brightness = state.brightness.filter(|b| applied.map(|a| a.brightness != Some(*b)).unwrap_or(true));
+    // 屏幕默认是打开的，状态未知时只发送关闭命令
+    let display_on = Some(state.display_on).filter(|on| applied.map(|a| a.display_on != *on).unwrap_or(!*on));
+    if render.is_wifi() {
+        let wifi = match wifi {
+            Some(wifi) => wifi,
+            None => return false,
+        };
+        // WiFi屏幕线程会在重新连接后再次发送
+        if let Some(brightness) = brightness {
+            let _ = wifi.send_message(wifi_screen::Message::Brightness(brightness));
+        }
+        if let Some(on) = display_on {
+            let _ = wifi.send_message(wifi_screen::Message::DisplayOn(on));
+        }
+        return true;
+    }
+    let screen = match usb_screen {
+        Some(screen) => screen,
+        None => return false,
+    };
+    // 只发送屏幕声明支持的控制命令
+    if let Some(brightness) = brightness {
+        if !screen.info().supports(usb_screen::FEAT_BACKLIGHT) {
+            warn!("屏幕{}不支持背光控制，忽略亮度设置", screen.info().label);
+        } else if let Err(err) = screen.set_brightness(brightness) {
+            error!("设置亮度失败:{err:?}");
+        }
+    }
+    if let Some(on) = display_on {
+        if !screen.info().supports(usb_screen::FEAT_POWER) {
+            warn!("屏幕{}不支持开关控制，忽略屏幕开关设置", screen.info().label);
+        } else if let Err(err) = screen.set_display_on(on) {
+            error!("设置屏幕开关失败:{err:?}");
+        }
+    }
+    true
+}
+
+fn frame_duration(fps: f32) -> u128 {
+    (1000. / fps) as u128
+}
+
+pub fn load_screen_file(file: &str, overrides: &ScreenOverrides) -> Result<ScreenRender> {
+    eprintln!("----------------------------------------");
+    eprintln!("正在打开屏幕文件: {}", file);
+    info!("打开屏幕文件:{file}");
+
+    // 检查文件是否存在
+    let file_path = Path::new(&file);
+    if !file_path.exists() {
+        eprintln!("错误: 文件不存在: {}", file);
+        return Err(anyhow!("文件不存在: {}", file));
+    }
+
+    // 读取文件
+    let f = match std::fs::read(file) {
+        Ok(data) => {
+            eprintln!("文件读取成功, 大小: {} 字节", data.len());
+            data
+        }
+        Err(e) => {
+            eprintln!("错误: 读取文件失败: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    // 解析screen文件
+    match ScreenRender::new_from_file(&f) {
+        Ok(mut r) => {
+            // 命令行参数覆盖文件中保存的参数
+            overrides.apply(&mut r);
+            eprintln!("screen文件解析成功");
+            eprintln!("  屏幕尺寸: {}x{}", r.width, r.height);
+            eprintln!("  帧率: {} fps", r.fps);
+            eprintln!("  旋转角度: {} 度", r.rotate_degree);
+            if let Some(ip) = &r.device_ip {
+                eprintln!("  设备IP: {}", ip);
+            }
+            if let Some(id) = &r.device_id {
+                eprintln!("  设备ID: {}", id);
+            }
+            if let Some(address) = &r.device_address {
+                eprintln!("  设备地址: {}", address);
+            }
+            Ok(r)
+        }
+        Err(e) => {
+            eprintln!("错误: 解析screen文件失败: {}", e);
+            Err(e)
+        }
+    }
+}
+
+// 监视screen文件的修改时间，文件被替换或修改后重新加载
+struct ScreenFileWatcher {
+    path: PathBuf,
+    modified: Option<(SystemTime, u64)>,
+    last_check: Instant,
+}
+
+impl ScreenFileWatcher {
+    fn new(file: &str) -> Self {
+        let path = PathBuf::from(file);
+        let modified = Self::read_modified(&path);
+        Self { path, modified, last_check: Instant::now() }
+    }
+
+    fn read_modified(path: &Path) -> Option<(SystemTime, u64)> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some((meta.modified().ok()?, meta.len()))
+    }
+
+    // 每秒检查一次，文件发生变化时返回true
+    fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < Duration::from_secs(1) {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let modified = Self::read_modified(&self.path);
+        // 文件正在被复制(暂时不存在)时不处理，等待下一次检查
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+pub fn run_screen(file: &str, overrides: &ScreenOverrides, mut render: ScreenRender, control: Option<ScreenControl>) -> Result<()>{
+    // 设置监控
+    if let Err(e) = render.setup_monitor() {
+        eprintln!("警告: 设置监控失败: {}", e);
+    } else {
+        eprintln!("监控设置成功");
+    }
+
+    let mut usb_screen = None;
+    // 每个屏幕使用自己的WiFi连接，同一个进程可以驱动多个WiFi屏幕
+    let mut wifi: Option<WifiScreen> = None;
+
+    if render.is_wifi(){
+        if let Some(id) = render.device_id.as_ref() {
+            eprintln!("使用WiFi屏幕模式, 设备ID: {}", id);
+        } else if let Some(ip) = render.device_ip.as_ref() {
+            eprintln!("使用WiFi屏幕模式, IP: {}", ip);
+        }
+        info!("设置了ip地址，使用wifi屏幕..");
+    }else {
+        eprintln!("使用USB屏幕模式, 正在查找USB设备...");
+        info!("未设置ip地址，使用 USB屏幕...");
+        if let Some(address) = render.device_address.as_ref() {
+            eprintln!("绑定设备地址: {}", address);
+        }
+        usb_screen = open_usb_screen(render.device_address.as_deref());
+        if let Some(screen) = usb_screen.as_ref() {
+            eprintln!("USB屏幕设备已找到并打开: {}", screen.info().label);
+        } else {
+            eprintln!("警告: 未找到USB屏幕设备, 将在主循环中重试");
+        }
+    }
+
+    // 已经发送到屏幕的亮度和开关状态
+    let mut applied_display_state: Option<DisplayState> = None;
+    // 休眠计划: 每秒检查一次
+    let mut sleeping: Option<SleepReason> = None;
+    let mut last_schedule_check: Option<Instant> = None;
+    let mut idle_timer = IdleTimer::default();
+    // 上一次连接WiFi屏幕的时间
+    let mut last_connect: Option<Instant> = None;
+    // 认证失败只记录一次日志，重新连接后再次失败时才会记录
+    let mut auth_failed_logged = false;
+    // 没有找到USB屏幕时等待设备插入，每次只等待一小段时间，不阻塞主循环
+    let mut device_waiter: Option<DeviceWaiter> = None;
+    // 休眠方式为黑屏时，进入休眠后只发送一次黑色的帧
+    let mut blank_sent = false;
+
+    info!("USB Screen是否已打开: {}", usb_screen.is_some());
+    eprintln!("进入主循环...");
+    let mut last_draw_time = Instant::now();
+    let mut frame_duration = frame_duration(render.fps);
+    info!("帧时间:{}ms", frame_duration);
+    let mut watcher = ScreenFileWatcher::new(file);
+    loop {
+        if watcher.changed() {
+            // 重新加载失败时(例如文件只复制了一半)继续使用原来的布局，文件再次变化时重试
+            match load_screen_file(file, overrides) {
+                Ok(mut new_render) => {
+                    // 新增的组件类型需要启动对应的监控
+                    if let Err(e) = new_render.setup_monitor() {
+                        eprintln!("警告: 设置监控失败: {}", e);
+                    }
+                    // 新的设备绑定和其他屏幕冲突时继续使用原来的设备
+                    if let Err(err) = bind_device(file, &new_render) {
+                        eprintln!("警告: {err}，继续使用原来的设备");
+                        warn!("重新加载screen文件时设备绑定冲突:{err}");
+                        new_render.device_address = render.device_address.clone();
+                        new_render.device_ip = render.device_ip.clone();
+                        new_render.device_id = render.device_id.clone();
+                    }
+                    // 设备ID没有变化时继续使用已经查找到的地址
+                    if new_render.device_id.is_some() && new_render.device_id == render.device_id {
+                        new_render.device_ip = render.device_ip.clone();
+                    }
+                    // 设备绑定改变时才断开当前连接
+                    if new_render.device_ip != render.device_ip || new_render.device_id != render.device_id {
+                        // 断开并释放连接，上传线程随后退出
+                        if let Some(wifi) = wifi.take() {
+                            let _ = wifi.send_message(wifi_screen::Message::Disconnect);
+                        }
+                        if new_render.is_wifi() {
+                            usb_screen = None;
+                        }
+                        applied_display_state = None;
+                    }
+                    if new_render.device_address != render.device_address {
+                        usb_screen = None;
+                        applied_display_state = None;
+                        device_waiter = None;
+                    }
+                    // 绑定的设备改变后更新预留，其他屏幕可以打开原来的设备
+                    if reserved_address(&new_render) != reserved_address(&render) {
+                        if let Some(address) = reserved_address(&render) {
+                            usb_screen::release_device_address(address);
+                        }
+                        if let Some(address) = reserved_address(&new_render) {
+                            usb_screen::reserve_device_address(address);
+                        }
+                    }
+                    if new_render.scaling != render.scaling {
+                        if let Some(wifi) = wifi.as_ref() {
+                            let _ = wifi.send_message(wifi_screen::Message::Scaling(new_render.scaling));
+                        }
+                    }
+                    if new_render.wifi_codec != render.wifi_codec {
+                        if let Some(wifi) = wifi.as_ref() {
+                            let _ = wifi.send_message(wifi_screen::Message::Codec(new_render.wifi_codec));
+                        }
+                    }
+                    // 修改了令牌或证书后重新连接
+                    if new_render.wifi_security != render.wifi_security {
+                        if let Some(wifi) = wifi.as_ref() {
+                            let _ = wifi.send_message(wifi_screen::Message::Security(new_render.wifi_security.clone()));
+                            let _ = wifi.send_message(wifi_screen::Message::Disconnect);
+                        }
+                    }
+                    // 重新加载后保持当前的屏幕开关状态
+                    new_render.display_on = render.display_on;
+                    if new_render.fps != render.fps {
+                        frame_duration = self::frame_duration(new_render.fps);
+                        set_frame_duration(file, frame_duration);
+                    }
+                    render = new_render;
+                    eprintln!("screen文件已重新加载: {file}");
+                    info!("重新加载screen文件:{file} 帧时间:{}ms", frame_duration);
+                }
+                Err(err) => {
+                    error!("重新加载screen文件失败:{err:?}");
+                }
+            }
+        }
+        // 处理控制接口的请求
+        if let Some(control) = control.as_ref() {
+            control.process(&mut render);
+            // 控制接口修改了帧率
+            if self::frame_duration(render.fps) != frame_duration {
+                frame_duration = self::frame_duration(render.fps);
+                set_frame_duration(file, frame_duration);
+            }
+        }
+        if last_schedule_check.map(|t| t.elapsed() >= Duration::from_secs(1)).unwrap_or(true) {
+            last_schedule_check = Some(Instant::now());
+            let next = render.schedule.as_ref().and_then(|s| s.check(Local::now().time(), sleeping, &mut idle_timer));
+            if next != sleeping {
+                info!("屏幕休眠状态: {sleeping:?} -> {next:?}");
+                // 安静时段停止采集系统信息，空闲休眠需要继续采集CPU占用率用于唤醒
+                let quiet = |s: Option<SleepReason>| s == Some(SleepReason::QuietHours);
+                if quiet(next) != quiet(sleeping) {
+                    let _ = monitor::set_screen_sleeping(quiet(next));
+                }
+                sleeping = next;
+                blank_sent = false;
+            }
+        }
+        // screen文件、控制接口或者休眠计划修改了亮度和开关
+        let display_state = DisplayState::of(&render, sleeping, usb_screen.as_ref());
+        if applied_display_state != Some(display_state)
+            && apply_display_state(&render, display_state, usb_screen.as_ref(), wifi.as_ref(), applied_display_state) {
+            applied_display_state = Some(display_state);
+        }
+        if last_draw_time.elapsed().as_millis() < frame_duration{
+            std::thread::sleep(Duration::from_millis(5));
+            continue;
+        }
+        last_draw_time = Instant::now();
+        let frame = if sleeping.is_some() {
+            // 休眠时不渲染
+            if blank_sent || sleep_action(&render, usb_screen.as_ref()) != SleepAction::Blank {
+                continue;
+            }
+            blank_sent = true;
+            let frame = render.rotated_frame();
+            RgbImage::new(frame.width(), frame.height())
+        } else {
+            render.render();
+            //旋转
+            render.rotated_frame()
+        };
+        if let Some(control) = control.as_ref() {
+            control.set_last_frame(&frame);
+        }
+        // let rgb565 = rgb888_to_rgb565_u16(&frame, frame.width() as usize, frame.height() as usize);
+        if !render.display_on {
+            // 屏幕已关闭，不发送画面
+            continue;
+        }
+        if render.is_wifi(){
+            let wifi = match wifi.as_ref() {
+                Some(wifi) => wifi,
+                None => {
+                    let new_wifi = WifiScreen::new()?;
+                    last_connect = None;
+                    let _ = new_wifi.send_message(wifi_screen::Message::Scaling(render.scaling));
+                    let _ = new_wifi.send_message(wifi_screen::Message::Codec(render.wifi_codec));
+                    let _ = new_wifi.send_message(wifi_screen::Message::Security(render.wifi_security.clone()));
+                    wifi.insert(new_wifi)
+                }
+            };
+            //连接wifi屏幕
+            if let Ok(wifi_scr_status) = wifi.get_status(){
+                if !matches!(wifi_scr_status.status, wifi_screen::Status::AuthFailed) {
+                    auth_failed_logged = false;
+                }
+                match wifi_scr_status.status{
+                    wifi_screen::Status::NotConnected | wifi_screen::Status::ConnectFail
+                    | wifi_screen::Status::Disconnected => {
+                        // 定时重新连接，不阻塞渲染循环; 屏幕重新上线时可能换了IP地址
+                        if last_connect.map(|t| t.elapsed() >= WIFI_RECONNECT_INTERVAL).unwrap_or(true) {
+                            last_connect = Some(Instant::now());
+                            if let Some(msg) = connect_message(&render) {
+                                let _ = wifi.send_message(msg);
+                            }
+                        }
+                    }
+                    wifi_screen::Status::Connected => {
+                        // 使用 try_send 避免阻塞，如果上一帧还在发送中则跳过当前帧
+                        // 这样可以始终发送最新帧，提高响应速度
+                        let _ = wifi.try_send_message(wifi_screen::Message::Image(frame.convert()));
+                    }
+                    wifi_screen::Status::Connecting => {
+
+                    }
+                    wifi_screen::Status::AuthFailed => {
+                        // 令牌或证书错误时重试也不会成功，修改screen文件后重新连接
+                        if !auth_failed_logged {
+                            auth_failed_logged = true;
+                            warn!("WiFi屏幕认证失败，请检查screen文件中的令牌或证书: {}", wifi_scr_status.error.as_deref().unwrap_or_default());
+                        }
+                    }
+                }
+            }
+        }else{
+            if usb_screen.is_none() {
+                // 等待USB设备插入后再查找
+                if device_waiter.as_mut().map(|w| w.wait()).unwrap_or(true) {
+                    info!("open USB Screen... device_address={:?}", render.device_address);
+                    let waiter = DeviceWaiter::start();
+                    usb_screen = open_usb_screen(render.device_address.as_deref());
+                    // 新打开的屏幕需要重新发送黑屏
+                    blank_sent = false;
+                    device_waiter = if usb_screen.is_none() { Some(waiter) } else { None };
+                }
+            } else {
+                // 交给发送线程，不等待传输完成
+                let screen = usb_screen.as_ref().unwrap();
+                if let Err(err) = screen.send(frame) {
+                    error!("屏幕绘制失败:{err:?} {:?}", screen.stats());
+                    usb_screen = None;
+                    applied_display_state = None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::{Duration, Instant};
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+use crate::monitor;
+
+// 屏幕休眠计划: 在安静时段(例如 23:00~07:00)或者主机空闲时关闭屏幕
+// 休眠期间停止渲染，安静时段内还会停止系统信息采集
+
+// 空闲判断的默认CPU占用率阈值(%)
+const DEFAULT_IDLE_CPU_PERCENT: f32 = 5.;
+// 休眠后CPU占用率超过阈值时唤醒，取最近几秒的采样
+const WAKE_WINDOW_SECS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum SleepAction {
+    // 关闭屏幕(背光)
+    #[default]
+    Off,
+    // 显示黑屏，用于不支持关闭命令的老固件
+    Blank,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ScreenSchedule {
+    // 安静时段的开始和结束时间(HH:MM，本地时间)，结束时间小于开始时间时跨越午夜
+    pub quiet_start: Option<String>,
+    pub quiet_end: Option<String>,
+    // CPU占用率连续低于阈值多少分钟后休眠，为空时不检查空闲
+    pub idle_minutes: Option<u32>,
+    pub idle_cpu_percent: Option<f32>,
+    #[serde(default)]
+    pub action: SleepAction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SleepReason {
+    QuietHours,
+    Idle,
+}
+
+// 空闲计时: CPU占用率从什么时候开始持续低于阈值，保存在运行循环中
+// 不使用监控历史判断，空闲时间可以超过历史记录的长度
+#[derive(Default)]
+pub struct IdleTimer {
+    since: Option<Instant>,
+}
+
+impl ScreenSchedule {
+    // 解析 "23:00-07:00" 格式的安静时段
+    pub fn parse_quiet_hours(s: &str) -> Result<(String, String), String> {
+        let (start, end) = s.split_once('-').ok_or(format!("安静时段格式为 HH:MM-HH:MM: {s}"))?;
+        for t in [start, end] {
+            parse_time(t).ok_or(format!("无效的时间: {t}"))?;
+        }
+        Ok((start.trim().to_string(), end.trim().to_string()))
+    }
+
+    fn quiet_hours(&self) -> Option<(NaiveTime, NaiveTime)> {
+        Some((parse_time(self.quiet_start.as_ref()?)?, parse_time(self.quiet_end.as_ref()?)?))
+    }
+
+    pub fn in_quiet_hours(&self, now: NaiveTime) -> bool {
+        match self.quiet_hours() {
+            Some((start, end)) if start <= end => now >= start && now < end,
+            Some((start, end)) => now >= start || now < end,
+            None => false,
+        }
+    }
+
+    fn idle_cpu_percent(&self) -> f64 {
+        self.idle_cpu_percent.unwrap_or(DEFAULT_IDLE_CPU_PERCENT) as f64
+    }
+
+    // 空闲判断需要采集CPU占用率
+    pub fn needs_cpu_usage(&self) -> bool {
+        self.idle_minutes.is_some()
+    }
+
+    // 返回屏幕当前是否应该休眠，sleeping 为当前的休眠状态，每秒调用一次
+    pub fn check(&self, now: NaiveTime, sleeping: Option<SleepReason>, idle: &mut IdleTimer) -> Option<SleepReason> {
+        if self.in_quiet_hours(now) {
+            // 从安静时段唤醒后重新计时，不会立即进入空闲休眠
+            idle.since = None;
+            return Some(SleepReason::QuietHours);
+        }
+        let cpu_usage: Vec<f64> = monitor::history("cpu_usage", WAKE_WINDOW_SECS)
+            .iter()
+            .map(|(_, v)| *v)
+            .collect();
+        self.check_idle(&cpu_usage, sleeping, idle, Instant::now())
+    }
+
+    // cpu_usage 为最近几秒的CPU占用率采样
+    fn check_idle(&self, cpu_usage: &[f64], sleeping: Option<SleepReason>, idle: &mut IdleTimer, now: Instant) -> Option<SleepReason> {
+        let idle_minutes = self.idle_minutes?;
+        if cpu_usage.iter().any(|v| *v >= self.idle_cpu_percent()) {
+            // CPU占用率升高，唤醒并重新计时
+            idle.since = None;
+            return None;
+        }
+        if sleeping == Some(SleepReason::Idle) {
+            return Some(SleepReason::Idle);
+        }
+        if cpu_usage.is_empty() {
+            // 程序刚启动还没有采样
+            idle.since = None;
+            return None;
+        }
+        let since = *idle.since.get_or_insert(now);
+        if now.duration_since(since) >= Duration::from_secs(idle_minutes.max(1) as u64 * 60) {
+            Some(SleepReason::Idle)
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()
+}
+
+#[test]
+fn test_quiet_hours() {
+    let time = |s| parse_time(s).unwrap();
+    let mut schedule = ScreenSchedule::default();
+    assert!(!schedule.in_quiet_hours(time("23:30")));
+
+    // 跨越午夜
+    schedule.quiet_start = Some("23:00".to_string());
+    schedule.quiet_end = Some("07:00".to_string());
+    assert!(schedule.in_quiet_hours(time("23:00")));
+    assert!(schedule.in_quiet_hours(time("03:00")));
+    assert!(!schedule.in_quiet_hours(time("07:00")));
+    assert!(!schedule.in_quiet_hours(time("12:00")));
+
+    // 同一天内
+    schedule.quiet_start = Some("12:00".to_string());
+    schedule.quiet_end = Some("13:30".to_string());
+    assert!(schedule.in_quiet_hours(time("12:45")));
+    assert!(!schedule.in_quiet_hours(time("13:30")));
+    assert!(!schedule.in_quiet_hours(time("23:30")));
+
+    assert_eq!(
+        ScreenSchedule::parse_quiet_hours("23:00-07:00"),
+        Ok(("23:00".to_string(), "07:00".to_string()))
+    );
+    assert!(ScreenSchedule::parse_quiet_hours("23:00").is_err());
+    assert!(ScreenSchedule::parse_quiet_hours("25:00-07:00").is_err());
+}
+
+#[test]
+fn test_idle() {
+    let schedule = ScreenSchedule { idle_minutes: Some(90), ..Default::default() };
+    let mut idle = IdleTimer::default();
+    let start = Instant::now();
+    let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+
+    // 没有采样时不休眠
+    assert_eq!(schedule.check_idle(&[], None, &mut idle, at(0)), None);
+    assert_eq!(schedule.check_idle(&[1.], None, &mut idle, at(0)), None);
+    assert_eq!(schedule.check_idle(&[1.], None, &mut idle, at(89)), None);
+    // 空闲时间超过监控历史的长度(1小时)也能休眠
+    assert_eq!(schedule.check_idle(&[1.], None, &mut idle, at(90)), Some(SleepReason::Idle));
+    assert_eq!(schedule.check_idle(&[1.], Some(SleepReason::Idle), &mut idle, at(91)), Some(SleepReason::Idle));
+
+    // CPU占用率升高时唤醒，重新计时
+    assert_eq!(schedule.check_idle(&[1., 50.], Some(SleepReason::Idle), &mut idle, at(92)), None);
+    assert_eq!(schedule.check_idle(&[1.], None, &mut idle, at(93)), None);
+    assert_eq!(schedule.check_idle(&[1.], None, &mut idle, at(182)), None);
+    assert_eq!(schedule.check_idle(&[1.], None, &mut idle, at(183)), Some(SleepReason::Idle));
+}
@@ -1,12 +1,16 @@
 use std::{collections::HashMap, path::PathBuf};
 
 use crate::{
-    monitor::{self, WebcamInfo},
-    nmc::CITIES,
-    widgets::{ImageWidget, ProgressWidget, SaveableWidget, TextWidget, Widget},
+    data_source::{self, SourceScreen},
+    monitor,
+    schedule::ScreenSchedule,
+    widgets::{ChartWidget, CpuCoresWidget, ImageWidget, ProgressWidget, SaveableWidget, TextWidget, Widget},
+    wifi_screen::{Scaling, WifiCodec},
+    wifi_security::WifiSecurity,
 };
 use anyhow::{anyhow, Result};
 use async_std::fs;
+use image::{buffer::ConvertBuffer, RgbImage};
 use log::info;
 use lz4_flex::{compress_prepend_size, decompress_size_prepended};
 use crate::offscreen_canvas::{Font, FontSettings, OffscreenCanvas, BLACK};
@@ -36,6 +40,24 @@ pub struct SaveableScreen {
     pub rotate_degree: Option<i32>,
     //指定设备IP地址
     pub device_ip: Option<String>,
+    //WiFi屏幕的设备ID，设置后通过局域网发现查找屏幕的IP地址(IP变化后也能找到)
+    #[serde(default)]
+    pub device_id: Option<String>,
+    //屏幕背光亮度(0~100)，不设置时不修改屏幕的亮度
+    #[serde(default)]
+    pub brightness: Option<u8>,
+    //屏幕休眠计划(安静时段、空闲休眠)
+    #[serde(default)]
+    pub schedule: Option<ScreenSchedule>,
+    //WiFi屏幕分辨率和画面不一致时的缩放方式
+    #[serde(default)]
+    pub scaling: Scaling,
+    //WiFi屏幕的编码偏好(是否允许有损的JPEG关键帧)
+    #[serde(default)]
+    pub wifi_codec: WifiCodec,
+    //WiFi屏幕的加密(wss)和令牌
+    #[serde(default)]
+    pub wifi_security: WifiSecurity,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -60,6 +82,14 @@ pub struct ScreenRender {
     pub rotate_degree: i32,
     pub device_address: Option<String>,
     pub device_ip: Option<String>,
+    pub device_id: Option<String>,
+    pub brightness: Option<u8>,
+    pub schedule: Option<ScreenSchedule>,
+    pub scaling: Scaling,
+    pub wifi_codec: WifiCodec,
+    pub wifi_security: WifiSecurity,
+    // 屏幕是否打开，不保存到screen文件
+    pub display_on: bool,
 }
 
 impl ScreenRender {
@@ -86,9 +116,21 @@ impl ScreenRender {
             fps: 10.,
             device_address: None,
             device_ip: None,
+            device_id: None,
+            brightness: None,
+            schedule: None,
+            scaling: Scaling::default(),
+            wifi_codec: WifiCodec::default(),
+            wifi_security: WifiSecurity::default(),
+            display_on: true,
         })
     }
 
+    // 设置了IP地址或者设备ID时使用WiFi屏幕
+    pub fn is_wifi(&self) -> bool{
+        self.device_ip.is_some() || self.device_id.is_some()
+    }
+
     pub fn is_vertical(&self) -> bool{
         self.rotate_degree == 90 || self.rotate_degree == 270
     }
@@ -97,6 +139,20 @@ impl ScreenRender {
         self.rotate_degree == 0 || self.rotate_degree == 180
     }
 
+    // 将画布转换为RGB图像，并按照 rotate_degree 旋转，得到发送到屏幕的帧
+    pub fn rotated_frame(&self) -> RgbImage {
+        let frame: RgbImage = self.canvas.image_data().convert();
+        if self.rotate_degree == 90 {
+            image::imageops::rotate90(&frame)
+        }else if self.rotate_degree == 180{
+            image::imageops::rotate180(&frame)
+        }else if self.rotate_degree == 270{
+            image::imageops::rotate270(&frame)
+        }else{
+            frame
+        }
+    }
+
     pub fn set_font(&mut self, font_file: Option<&[u8]>, font_name: String) -> Result<()> {
         let font_file_clone = font_file.clone();
         let font_file = font_file.unwrap_or(DEFAULT_FONT);
@@ -110,53 +166,18 @@ impl ScreenRender {
 
     pub fn setup_monitor(&mut self) -> Result<()> {
         //在点击的地方添加一个对象
+        let screen = SourceScreen {
+            width: self.width,
+            height: self.height,
+            fps: self.fps,
+        };
         for widget in &mut self.widgets {
             info!("setup_monitor:{}", widget.type_name());
-            match widget.type_name() {
-                "memory" | "memory_total" | "memory_percent" | "swap" | "swap_percent" => {
-                    monitor::watch_memory(true)?
-                }
-                "webcam" =>{
-                    if let Some(widget) = widget.as_any_mut().downcast_mut::<ImageWidget>() {
-                        info!("webcam: tag1={:?}", widget.tag1);
-                        monitor::watch_webcam(Some(WebcamInfo{
-                            width: self.width,
-                            height: self.height,
-                            index: widget.tag1.as_ref().unwrap_or(&String::new()).parse().unwrap_or(0),
-                            fps: self.fps as u32
-                        }))?
-                    }
-                }
-                "cpu" | "cpu_usage" | "num_cpu" => monitor::watch_cpu(true)?,
-                "cpu_freq" => monitor::watch_cpu_clock_speed(true)?,
-                "cpu_temp." => monitor::watch_cpu_temperatures(true)?,
-                "cpu_cores_power" | "gpu_cores_power" => monitor::watch_cpu_power(true)?,
-                "cpu_package_power" | "gpu_package_power" => monitor::watch_cpu_power(true)?,
-                "cpu_fan" => monitor::watch_cpu_fan(true)?,
-                "gpu_fan" => monitor::watch_gpu_fan(true)?,
-                "gpu_clock" => monitor::watch_gpu_clock_speed(true)?,
-                "gpu_load" | "gpu_memory_load" | "gpu_memory_total_mb" | "gpu_memory_total_gb" => monitor::watch_gpu_load(true)?,
-                "gpu_temp." => monitor::watch_gpu_temperatures(true)?,
-                "num_process" => monitor::watch_process(true)?,
-                "disk_usage" => monitor::watch_disk(true)?,
-                "net_ip" | "net_ip_info" => monitor::watch_net_ip(true)?,
-                "disk_read_speed" => monitor::watch_disk_speed(true)?,
-                "disk_write_speed" => monitor::watch_disk_speed(true)?,
-                "received_speed" => monitor::watch_network_speed(true)?,
-                "transmitted_speed" => monitor::watch_network_speed(true)?,
-                "weather" => {
-                    if let Some(widget) = widget.as_any_mut().downcast_mut::<TextWidget>() {
-                        if widget.tag2.len() > 0 {
-                            //查询对应的城市
-                            info!("更新天气，查询对应的城市: tag2={}", widget.tag2);
-                            if let Some(city) = CITIES.iter().find(|c| c.city == widget.tag2) {
-                                monitor::watch_weather(Some(city.clone()))?
-                            }
-                        }
-                    }
-                }
-                _ => (),
-            }
+            data_source::setup(widget.as_mut(), &screen)?;
+        }
+        // 空闲休眠根据CPU占用率判断
+        if self.schedule.as_ref().map(|s| s.needs_cpu_usage()).unwrap_or(false) {
+            monitor::watch_cpu(true)?;
         }
         Ok(())
     }
@@ -181,6 +202,8 @@ impl ScreenRender {
         }
     }
 
+    // 编辑器中添加组件
+    #[cfg(feature = "editor")]
     pub fn add_widget(
         &mut self,
         type_name: &str,
@@ -197,6 +220,11 @@ impl ScreenRender {
         } else if type_name == "ring_progress" {
             // 环形进度条默认使用 cpu_usage 数据源
             Box::new(ProgressWidget::new(x, y, "cpu_usage", crate::widgets::ProgressType::Ring))
+        } else if type_name == "line_chart" {
+            // 图表默认显示 cpu_usage 的历史数据
+            Box::new(ChartWidget::new(x, y, "cpu_usage"))
+        } else if type_name == "cpu_cores" {
+            Box::new(CpuCoresWidget::new(x, y))
         } else {
             let mut text_index = 1;
             for w in self.widgets.iter_mut() {
@@ -262,6 +290,12 @@ impl ScreenRender {
         self.rotate_degree = saveable.rotate_degree.unwrap_or(0);
         self.device_address = saveable.device_address;
         self.device_ip = saveable.device_ip;
+        self.device_id = saveable.device_id;
+        self.brightness = saveable.brightness;
+        self.schedule = saveable.schedule;
+        self.scaling = saveable.scaling;
+        self.wifi_codec = saveable.wifi_codec;
+        self.wifi_security = saveable.wifi_security;
         self.canvas =
             OffscreenCanvas::new(saveable.width, saveable.height, self.canvas.font().clone());
         if let Some(font) = saveable.font {
@@ -279,6 +313,12 @@ impl ScreenRender {
                 SaveableWidget::ProgressWidget(prog) => {
                     self.widgets.push(Box::new(prog));
                 }
+                SaveableWidget::ChartWidget(chart) => {
+                    self.widgets.push(Box::new(chart));
+                }
+                SaveableWidget::CpuCoresWidget(cores) => {
+                    self.widgets.push(Box::new(cores));
+                }
             }
         }
         Ok(())
@@ -301,6 +341,12 @@ impl ScreenRender {
         render.fps = saveable.fps;
         render.device_address = saveable.device_address;
         render.device_ip = saveable.device_ip;
+        render.device_id = saveable.device_id;
+        render.brightness = saveable.brightness;
+        render.schedule = saveable.schedule;
+        render.scaling = saveable.scaling;
+        render.wifi_codec = saveable.wifi_codec;
+        render.wifi_security = saveable.wifi_security;
         render.rotate_degree = saveable.rotate_degree.unwrap_or(0);
         render.widgets.clear();
         for w in saveable.widgets {
@@ -314,6 +360,12 @@ impl ScreenRender {
                 SaveableWidget::ProgressWidget(prog) => {
                     render.widgets.push(Box::new(prog));
                 }
+                SaveableWidget::ChartWidget(chart) => {
+                    render.widgets.push(Box::new(chart));
+                }
+                SaveableWidget::CpuCoresWidget(cores) => {
+                    render.widgets.push(Box::new(cores));
+                }
             }
         }
         Ok(render)
@@ -337,6 +389,12 @@ impl ScreenRender {
             fps: self.fps,
             device_address: self.device_address.clone(),
             device_ip: self.device_ip.clone(),
+            device_id: self.device_id.clone(),
+            brightness: self.brightness,
+            schedule: self.schedule.clone(),
+            scaling: self.scaling,
+            wifi_codec: self.wifi_codec,
+            wifi_security: self.wifi_security.clone(),
         };
         for idx in 0..self.widgets.len() {
             if let Some(widget) = self.widgets[idx].as_any_mut().downcast_mut::<TextWidget>() {
@@ -354,6 +412,16 @@ impl ScreenRender {
                     .widgets
                     .push(SaveableWidget::ProgressWidget(widget.clone()));
             }
+            if let Some(widget) = self.widgets[idx].as_any_mut().downcast_mut::<ChartWidget>() {
+                saveable
+                    .widgets
+                    .push(SaveableWidget::ChartWidget(widget.clone()));
+            }
+            if let Some(widget) = self.widgets[idx].as_any_mut().downcast_mut::<CpuCoresWidget>() {
+                saveable
+                    .widgets
+                    .push(SaveableWidget::CpuCoresWidget(widget.clone()));
+            }
         }
         let json = serde_json::to_string(&saveable)?;
         let contents = json.as_bytes();
@@ -381,7 +449,13 @@ impl ScreenRender {
             widgets: vec![],
             fps: self.fps,
             device_address: self.device_address.clone(),
-            device_ip: self.device_ip.clone()
+            device_ip: self.device_ip.clone(),
+            device_id: self.device_id.clone(),
+            brightness: self.brightness,
+            schedule: self.schedule.clone(),
+            scaling: self.scaling,
+            wifi_codec: self.wifi_codec,
+            wifi_security: self.wifi_security.clone(),
         };
         for idx in 0..self.widgets.len() {
             if let Some(widget) = self.widgets[idx].as_any_mut().downcast_mut::<TextWidget>() {
@@ -399,6 +473,16 @@ impl ScreenRender {
                     .widgets
                     .push(SaveableWidget::ProgressWidget(widget.clone()));
             }
+            if let Some(widget) = self.widgets[idx].as_any_mut().downcast_mut::<ChartWidget>() {
+                saveable
+                    .widgets
+                    .push(SaveableWidget::ChartWidget(widget.clone()));
+            }
+            if let Some(widget) = self.widgets[idx].as_any_mut().downcast_mut::<CpuCoresWidget>() {
+                saveable
+                    .widgets
+                    .push(SaveableWidget::CpuCoresWidget(widget.clone()));
+            }
         }
         Ok(saveable)
     }
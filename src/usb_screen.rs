@@ -1,33 +1,83 @@
 use std::time::{Duration, Instant};
 use std::io::{Read, Write};
-use std::sync::Mutex;
-use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::thread::JoinHandle;
+
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender, TrySendError};
 
 use futures_lite::future::block_on;
 use image::{Rgb, RgbImage};
-use log::{info, warn, debug};
-use nusb::Interface;
+use log::{info, warn, debug, error};
+use nusb::{hotplug::HotplugEvent, transfer::RequestBuffer, Interface};
 use anyhow::{anyhow, Result};
 use once_cell::sync::Lazy;
 #[cfg(feature = "usb-serial")]
 use serialport::{SerialPort, SerialPortInfo, SerialPortType};
 
+use crate::dirty_rect;
 use crate::rgb565::rgb888_to_rgb565_be;
+#[cfg(feature = "usb-serial")]
+use crate::wifi_screen::DeltaEncoder;
 
 // ESP32 WiFi 屏幕使用的高波特率
 const ESP32_BAUD_RATE: u32 = 2_000_000;
 // 普通串口屏幕使用的波特率
 const DEFAULT_BAUD_RATE: u32 = 115_200;
 
-// ESP32设备缓存: port_name -> (width, height)
+// ReadInfo探测结果: (width, height, FEAT字段中声明的功能)
+#[cfg(feature = "usb-serial")]
+type ReadInfo = (u16, u16, Vec<String>);
+
+// ESP32设备缓存: port_name -> ReadInfo探测结果
 // 用于避免重复探测已发现的设备（设备被打开后无法再次探测）
 #[cfg(feature = "usb-serial")]
-static ESP32_DEVICE_CACHE: Lazy<Mutex<HashMap<String, (u16, u16)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static ESP32_DEVICE_CACHE: Lazy<Mutex<HashMap<String, ReadInfo>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// ReadInfo探测失败的串口: port_name -> 失败次数
+// 失败两次后不再探测，直到串口消失(设备拔出)后重新出现
+#[cfg(feature = "usb-serial")]
+static NOT_SCREEN_PORTS: Lazy<Mutex<HashMap<String, u32>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+#[cfg(feature = "usb-serial")]
+const MAX_PROBE_FAILURES: u32 = 2;
+
+// USB热插拔事件计数，每次设备插入或拔出时加1并唤醒等待的屏幕线程
+type HotplugState = Arc<(Mutex<u64>, Condvar)>;
+// 不支持监听热插拔的平台为None，改为定时查找
+static HOTPLUG: Lazy<Option<HotplugState>> = Lazy::new(start_hotplug_watch);
+// 监听热插拔时，没有收到事件也会定期重新查找一次
+const HOTPLUG_RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+// 不支持热插拔监听时的查找间隔
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// 收到插入事件后等待驱动创建串口等设备节点
+const HOTPLUG_SETTLE_DELAY: Duration = Duration::from_millis(500);
+// 每次最多等待的时间，屏幕线程在等待期间不能处理重新加载、控制接口和休眠计划
+const WAIT_SLICE: Duration = Duration::from_millis(500);
+
+// 本进程已经打开的设备地址，未指定地址的屏幕不会再次打开这些设备
+static OPENED_DEVICES: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+// 被screen文件绑定的设备地址(地址 -> 绑定的屏幕数量)，只能由绑定它的屏幕打开
+static RESERVED_DEVICES: Lazy<Mutex<HashMap<String, usize>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 // use crate::rgb565::rgb888_to_rgb565_be;
 
 const BULK_OUT_EP: u8 = 0x01;
 const BULK_IN_EP: u8 = 0x81;
+// USB Raw 一次传输的最大数据量，超过后RP2040会死机; 新固件可以在能力查询中报告
+const MAX_USB_RAW_PAYLOAD: usize = 1024*28;
+// USB Raw 每帧最多发送的脏矩形数量，每个矩形都有单独的帧头和帧尾
+const MAX_DIRTY_RECTS: usize = 4;
+// 画面静止时也定期发送一次完整帧，屏幕重启或者丢帧后可以恢复
+const FULL_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+// 屏幕控制命令，和图像帧一样以8字节的magic开头，后面跟2字节的参数(Big-Endian)
+// 只发送给在能力查询(USB Raw)或者ReadInfo(ESP32)的FEAT字段中声明了对应功能的屏幕
+// 背光亮度: backlite + 亮度(0~100)
+const CMD_BACKLIGHT:u64 = u64::from_be_bytes(*b"backlite");
+pub const FEAT_BACKLIGHT: &str = "BACKLIGHT";
+// 屏幕开关: displayp + 1(打开)/0(关闭)，关闭时固件关闭背光并让屏幕进入睡眠
+const CMD_DISPLAY_POWER:u64 = u64::from_be_bytes(*b"displayp");
+pub const FEAT_POWER: &str = "POWER";
 
 #[derive(Clone, Debug)]
 pub struct UsbScreenInfo{
@@ -37,47 +87,297 @@ pub struct UsbScreenInfo{
     pub height: u16,
     // 是否是ESP32 WiFi屏幕(通过ReadInfo探测发现)，需要使用高波特率
     pub is_esp32_wifi: bool,
+    // 固件在ReadInfo中声明支持差分帧(FEAT:DELTA)
+    pub supports_delta: bool,
+    // USB Raw 固件在串号中声明支持能力查询(USBSCR320x240;CAPS)，没有声明的设备不发送查询
+    pub query_caps: bool,
+    // USB Raw 屏幕打开后查询到的能力，ESP32屏幕为ReadInfo中声明的功能，没有查询或者查询失败时为空
+    pub capabilities: Option<ScreenCapabilities>,
+}
+
+impl UsbScreenInfo{
+    // 屏幕是否声明支持某个编码或者功能
+    pub fn supports(&self, name: &str) -> bool{
+        self.capabilities.as_ref().map(|c| c.supports(name)).unwrap_or(false)
+    }
+}
+
+// USB Raw 屏幕的能力，只查询在串号中声明了 CAPS 的设备，通过 BULK_IN_EP 读取响应
+// 响应格式: USB-SCREEN;VER:{固件版本};PANEL:{屏幕型号};SIZE:{宽}x{高};MAX:{最大数据量};CODEC:LZ4,DELTA;FEAT:BACKLIGHT,POWER
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenCapabilities{
+    pub firmware_version: Option<String>,
+    pub panel_model: Option<String>,
+    pub size: Option<(u16, u16)>,
+    // 一次传输的最大数据量(压缩后)
+    pub max_payload: usize,
+    // 支持的编码，大写
+    pub codecs: Vec<String>,
+    // 支持的控制命令等功能，大写
+    pub features: Vec<String>,
+}
+
+impl Default for ScreenCapabilities{
+    fn default() -> Self{
+        Self{
+            firmware_version: None,
+            panel_model: None,
+            size: None,
+            max_payload: MAX_USB_RAW_PAYLOAD,
+            codecs: vec!["LZ4".to_string()],
+            features: vec![],
+        }
+    }
+}
+
+impl ScreenCapabilities{
+    fn parse(line: &str) -> Option<Self>{
+        let mut parts = line.trim_matches(|c: char| c.is_whitespace() || c == '\0').split(';');
+        if !parts.next()?.trim().eq_ignore_ascii_case("USB-SCREEN"){
+            return None;
+        }
+        let mut caps = Self::default();
+        for part in parts{
+            let (key, value) = match part.split_once(':'){
+                Some((key, value)) => (key.trim().to_uppercase(), value.trim()),
+                None => continue,
+            };
+            match key.as_str(){
+                "VER" => caps.firmware_version = Some(value.to_string()),
+                "PANEL" => caps.panel_model = Some(value.to_string()),
+                "SIZE" => {
+                    let size = value.to_lowercase();
+                    caps.size = size.split_once('x')
+                        .and_then(|(w, h)| Some((w.parse::<u16>().ok()?, h.parse::<u16>().ok()?)))
+                        .filter(|(w, h)| *w > 0 && *h > 0);
+                }
+                "MAX" => {
+                    if let Ok(max) = value.parse::<usize>(){
+                        caps.max_payload = max.max(1024);
+                    }
+                }
+                "CODEC" => caps.codecs = parse_list(value),
+                "FEAT" => caps.features = parse_list(value),
+                // 忽略不认识的字段，方便固件以后增加
+                _ => (),
+            }
+        }
+        Some(caps)
+    }
+
+    // ESP32屏幕没有能力查询，只有ReadInfo中声明的功能
+    #[cfg(feature = "usb-serial")]
+    fn with_features(features: &[String]) -> Self{
+        Self{ features: features.to_vec(), ..Default::default() }
+    }
+
+    pub fn supports(&self, name: &str) -> bool{
+        self.codecs.iter().chain(self.features.iter()).any(|c| c.eq_ignore_ascii_case(name))
+    }
+}
+
+// 逗号分隔的列表，转换为大写
+fn parse_list(value: &str) -> Vec<String>{
+    value.split(',')
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+// 帧发送状态: 记录上一次发送成功的帧，跳过没有变化的帧
+#[derive(Default)]
+pub struct FrameState{
+    last_frame: Option<RgbImage>,
+    last_full_frame: Option<Instant>,
+    #[cfg(feature = "usb-serial")]
+    delta_encoder: Option<DeltaEncoder>,
+}
+
+impl FrameState{
+    fn need_full_frame(&self) -> bool{
+        self.last_frame.is_none()
+            || self.last_full_frame.map(|t| t.elapsed() >= FULL_REFRESH_INTERVAL).unwrap_or(true)
+    }
+
+    fn is_unchanged(&self, img: &RgbImage) -> bool{
+        !self.need_full_frame() && self.last_frame.as_ref() == Some(img)
+    }
+
+    fn sent(&mut self, img: &RgbImage, full: bool){
+        self.last_frame = Some(img.clone());
+        if full{
+            self.last_full_frame = Some(Instant::now());
+        }
+    }
 }
 
 pub enum UsbScreen{
-    USBRaw((UsbScreenInfo, Interface)),
+    USBRaw((UsbScreenInfo, Interface, FrameState)),
     #[cfg(feature = "usb-serial")]
-    USBSerial((UsbScreenInfo, Box<dyn SerialPort>))
+    USBSerial((UsbScreenInfo, Box<dyn SerialPort>, FrameState))
 }
 
 impl UsbScreen{
     pub fn draw_rgb_image(&mut self, x: u16, y: u16, img:&RgbImage) -> anyhow::Result<()>{
         //如果图像比屏幕大， 不绘制，否则会RP2040死机导致卡住
         match self{
-            UsbScreen::USBRaw((info, interface)) => {
+            UsbScreen::USBRaw((info, interface, state)) => {
                 if img.width() <= info.width as u32 && img.height() <= info.height as u32{
-                    draw_rgb_image(x, y, img, interface)?;
+                    if state.is_unchanged(img){
+                        return Ok(());
+                    }
+                    // 只发送和上一帧相比发生变化的区域
+                    let full = state.need_full_frame();
+                    let rects = match state.last_frame.take() {
+                        Some(last_frame) if !full => dirty_rect::diff(&last_frame, img, MAX_DIRTY_RECTS),
+                        _ => vec![dirty_rect::DirtyRect { x: 0, y: 0, width: img.width(), height: img.height() }],
+                    };
+                    let max_payload = info.capabilities.as_ref().map(|c| c.max_payload).unwrap_or(MAX_USB_RAW_PAYLOAD);
+                    for rect in rects {
+                        if rect.width == img.width() && rect.height == img.height() {
+                            draw_rgb_image_with_limit(x, y, img, max_payload, interface)?;
+                        } else {
+                            let part = image::imageops::crop_imm(img, rect.x, rect.y, rect.width, rect.height).to_image();
+                            draw_rgb_image_with_limit(x + rect.x as u16, y + rect.y as u16, &part, max_payload, interface)?;
+                        }
+                    }
+                    // 发送失败时 last_frame 为空，下一次发送整帧
+                    state.sent(img, full);
                 }
             }
 
             #[cfg(feature = "usb-serial")]
-            UsbScreen::USBSerial((info, port)) => {
+            UsbScreen::USBSerial((info, port, state)) => {
                 if img.width() <= info.width as u32 && img.height() <= info.height as u32{
+                    if state.is_unchanged(img){
+                        return Ok(());
+                    }
+                    let full = state.need_full_frame();
+                    state.last_frame = None;
                     let rgb565 = rgb888_to_rgb565_be(&img, img.width() as usize, img.height() as usize);
-                    if info.is_esp32_wifi {
+                    if info.supports_delta {
+                        // 固件支持差分帧，使用和WiFi相同的编码
+                        let encoder = state.delta_encoder.get_or_insert_with(|| DeltaEncoder::new(60));
+                        if full {
+                            encoder.reset();
+                        }
+                        let (frame, _frame_type) = encoder.encode(&rgb565, img.width() as u16, img.height() as u16);
+                        if let Err(err) = draw_delta_frame_serial(&frame, x, y, img.width() as u16, img.height() as u16, port.as_mut()) {
+                            // 屏幕可能没有收到参考帧，下一帧重新发送关键帧
+                            encoder.reset();
+                            return Err(err);
+                        }
+                    } else if info.is_esp32_wifi {
                         // ESP32设备使用合并发送方式（高速）
                         draw_rgb565_serial(&rgb565, x, y, img.width() as u16, img.height() as u16, port.as_mut())?;
                     } else {
                         // 老设备使用分段发送方式（兼容性更好）
                         draw_rgb565_serial_legacy(&rgb565, x, y, img.width() as u16, img.height() as u16, port.as_mut())?;
                     }
+                    state.sent(img, full);
                 }
             }
         }
         Ok(())
     }
 
+    pub fn info(&self) -> &UsbScreenInfo{
+        match self{
+            UsbScreen::USBRaw((info, _, _)) => info,
+            #[cfg(feature = "usb-serial")]
+            UsbScreen::USBSerial((info, _, _)) => info,
+        }
+    }
+
+    // 设置背光亮度(0~100)
+    pub fn set_brightness(&mut self, brightness: u8) -> anyhow::Result<()>{
+        if !self.info().supports(FEAT_BACKLIGHT){
+            return Err(anyhow!("屏幕不支持背光控制"));
+        }
+        self.send_command(CMD_BACKLIGHT, brightness.min(100) as u16)
+    }
+
+    // 打开或关闭屏幕
+    pub fn set_display_on(&mut self, on: bool) -> anyhow::Result<()>{
+        if !self.info().supports(FEAT_POWER){
+            return Err(anyhow!("屏幕不支持开关控制"));
+        }
+        self.send_command(CMD_DISPLAY_POWER, on as u16)?;
+        if on{
+            // 屏幕关闭期间可能丢失了画面，打开后发送完整帧
+            match self{
+                UsbScreen::USBRaw((_, _, state)) => *state = FrameState::default(),
+                #[cfg(feature = "usb-serial")]
+                UsbScreen::USBSerial((_, _, state)) => *state = FrameState::default(),
+            }
+        }
+        Ok(())
+    }
+
+    fn send_command(&mut self, cmd: u64, value: u16) -> anyhow::Result<()>{
+        let mut packet = [0u8; 10];
+        packet[0..8].copy_from_slice(&cmd.to_be_bytes());
+        packet[8..10].copy_from_slice(&value.to_be_bytes());
+        match self{
+            UsbScreen::USBRaw((_, interface, _)) => {
+                block_on(async {
+                    async_std::future::timeout(Duration::from_millis(100), interface.bulk_out(BULK_OUT_EP, packet.to_vec()))
+                        .await
+                })?.status?;
+            }
+            #[cfg(feature = "usb-serial")]
+            UsbScreen::USBSerial((_, port, _)) => {
+                port.write_all(&packet)?;
+                port.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // 打开指定的设备(绑定了地址的屏幕、编辑器中选择的屏幕)，可以打开被预留的设备
     pub fn open(info: UsbScreenInfo) -> Result<Self>{
+        Self::open_claimed(info, true)
+    }
+
+    fn open_claimed(info: UsbScreenInfo, bound: bool) -> Result<Self>{
+        let address = info.address.clone();
+        if !claim_device(&address, bound){
+            return Err(anyhow!("设备已经被占用:{address}"));
+        }
+        let screen = Self::open_device(info);
+        if screen.is_err(){
+            release_device(&address);
+        }
+        screen
+    }
+
+    fn open_device(info: UsbScreenInfo) -> Result<Self>{
         info!("打开屏幕:label={} addr={} {}x{} esp32={}", info.label, info.address, info.width, info.height, info.is_esp32_wifi);
         let addr = info.address.clone();
         if info.label.contains("Screen"){
             //USB Raw设备, addr是device_address
-            Ok(Self::USBRaw((info, open_usb_raw_device(&addr)?)))
+            let interface = open_usb_raw_device(&addr)?;
+            let mut info = info;
+            if info.query_caps{
+                match query_capabilities(&interface){
+                    Some(caps) => {
+                        info!("屏幕能力:{caps:?}");
+                        // 尺寸以串号为准，响应中的尺寸只用来检查
+                        if let Some((width, height)) = caps.size{
+                            if (width, height) != (info.width, info.height){
+                                warn!("屏幕报告的尺寸{width}x{height}和串号中的{}x{}不一致", info.width, info.height);
+                            }
+                        }
+                        // 图像帧只使用LZ4压缩
+                        if !caps.supports("LZ4"){
+                            return Err(anyhow!("屏幕不支持LZ4编码:{:?}", caps.codecs));
+                        }
+                        info.capabilities = Some(caps);
+                    }
+                    None => warn!("屏幕未响应能力查询，使用默认参数"),
+                }
+            }
+            Ok(Self::USBRaw((info, interface, FrameState::default())))
         }else{
             #[cfg(feature = "usb-serial")]
             {
@@ -87,7 +387,7 @@ impl UsbScreen{
                 let screen = serialport::new(&info.address, baud_rate)
                     .timeout(Duration::from_millis(100))
                     .open()?;
-                Ok(Self::USBSerial((info, screen)))
+                Ok(Self::USBSerial((info, screen, FrameState::default())))
             }
             #[cfg(not(feature = "usb-serial"))]
             {
@@ -97,17 +397,363 @@ impl UsbScreen{
     }
 }
 
+impl Drop for UsbScreen{
+    fn drop(&mut self){
+        release_device(&self.info().address);
+    }
+}
+
+// 发送统计的输出间隔
+const STATS_INTERVAL: Duration = Duration::from_secs(30);
+
+// 发送线程的统计数据
+#[derive(Default, Clone, Debug)]
+pub struct TransferStats{
+    // 发送成功的帧数
+    pub frames: u64,
+    // 发送线程忙碌时被新帧替换掉的帧数
+    pub dropped: u64,
+    // 最近一帧从提交到发送完成的时间(毫秒)
+    pub last_latency_ms: u128,
+    // 最近一帧的传输时间(毫秒)
+    pub last_transfer_ms: u128,
+    total_transfer_ms: u128,
+    pub max_transfer_ms: u128,
+}
+
+impl TransferStats{
+    pub fn avg_transfer_ms(&self) -> u128{
+        if self.frames == 0 { 0 } else { self.total_transfer_ms / self.frames as u128 }
+    }
+}
+
+// 发送线程在两帧之间执行的屏幕控制命令
+enum ScreenCommand{
+    Brightness(u8),
+    DisplayOn(bool),
+}
+
+// 在后台线程中向USB屏幕发送图像，渲染不会被USB/串口传输阻塞
+// 队列长度为1，发送线程忙碌时新的帧替换掉还没发送的帧，始终发送最新的帧
+pub struct UsbScreenSender{
+    info: UsbScreenInfo,
+    sender: Sender<(RgbImage, Instant)>,
+    // 控制命令不能丢弃，单独使用一个队列
+    commands: Sender<ScreenCommand>,
+    // 用来丢弃队列中还没有发送的旧帧
+    receiver: Receiver<(RgbImage, Instant)>,
+    stats: Arc<Mutex<TransferStats>>,
+    error: Arc<Mutex<Option<String>>>,
+    handle: JoinHandle<()>,
+}
+
+impl UsbScreenSender{
+    pub fn start(mut screen: UsbScreen) -> Result<Self>{
+        let info = screen.info().clone();
+        let (sender, receiver) = bounded::<(RgbImage, Instant)>(1);
+        let (commands, command_receiver) = unbounded::<ScreenCommand>();
+        let stats = Arc::new(Mutex::new(TransferStats::default()));
+        let error = Arc::new(Mutex::new(None));
+        let thread_receiver = receiver.clone();
+        let thread_stats = stats.clone();
+        let thread_error = error.clone();
+        let handle = std::thread::Builder::new()
+            .name(format!("usb-screen-{}", info.address))
+            .spawn(move ||{
+                let mut last_report = Instant::now();
+                // 所有发送端关闭后退出，退出时释放设备
+                loop{
+                    let (frame, submit_time) = select!{
+                        recv(command_receiver) -> cmd => {
+                            let cmd = match cmd{
+                                Ok(cmd) => cmd,
+                                Err(_) => break,
+                            };
+                            let ret = match cmd{
+                                ScreenCommand::Brightness(brightness) => screen.set_brightness(brightness),
+                                ScreenCommand::DisplayOn(on) => screen.set_display_on(on),
+                            };
+                            // 只记录错误，不关闭屏幕
+                            if let Err(err) = ret{
+                                warn!("屏幕控制命令发送失败:{err:?}");
+                            }
+                            continue;
+                        }
+                        recv(thread_receiver) -> frame => match frame{
+                            Ok(frame) => frame,
+                            Err(_) => break,
+                        },
+                    };
+                    let start = Instant::now();
+                    if let Err(err) = screen.draw_rgb_image(0, 0, &frame){
+                        error!("屏幕绘制失败:{err:?}");
+                        if let Ok(mut e) = thread_error.lock(){
+                            *e = Some(format!("{err:?}"));
+                        }
+                        break;
+                    }
+                    if let Ok(mut stats) = thread_stats.lock(){
+                        let transfer_ms = start.elapsed().as_millis();
+                        stats.frames += 1;
+                        stats.last_transfer_ms = transfer_ms;
+                        stats.last_latency_ms = submit_time.elapsed().as_millis();
+                        stats.total_transfer_ms += transfer_ms;
+                        stats.max_transfer_ms = stats.max_transfer_ms.max(transfer_ms);
+                        if last_report.elapsed() >= STATS_INTERVAL{
+                            last_report = Instant::now();
+                            info!("屏幕{} 已发送:{}帧 丢弃:{}帧 平均传输:{}ms 最大传输:{}ms 最近延迟:{}ms",
+                                screen.info().label, stats.frames, stats.dropped, stats.avg_transfer_ms(),
+                                stats.max_transfer_ms, stats.last_latency_ms);
+                        }
+                    }
+                }
+            })?;
+        Ok(Self{ info, sender, commands, receiver, stats, error, handle })
+    }
+
+    // 提交一帧，不等待发送完成; 发送线程因为错误退出后返回错误，需要重新打开屏幕
+    pub fn send(&self, frame: RgbImage) -> Result<()>{
+        if self.handle.is_finished(){
+            let err = self.error.lock().ok().and_then(|e| e.clone()).unwrap_or_default();
+            return Err(anyhow!("屏幕发送线程已退出:{err}"));
+        }
+        match self.sender.try_send((frame, Instant::now())){
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(frame)) => {
+                // 替换掉还没有发送的旧帧
+                if self.receiver.try_recv().is_ok(){
+                    if let Ok(mut stats) = self.stats.lock(){
+                        stats.dropped += 1;
+                    }
+                }
+                let _ = self.sender.try_send(frame);
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(anyhow!("屏幕发送线程已退出")),
+        }
+    }
+
+    // 设置背光亮度(0~100)，在发送线程中执行
+    pub fn set_brightness(&self, brightness: u8) -> Result<()>{
+        self.commands.send(ScreenCommand::Brightness(brightness)).map_err(|_| anyhow!("屏幕发送线程已退出"))
+    }
+
+    // 打开或关闭屏幕，在发送线程中执行
+    pub fn set_display_on(&self, on: bool) -> Result<()>{
+        self.commands.send(ScreenCommand::DisplayOn(on)).map_err(|_| anyhow!("屏幕发送线程已退出"))
+    }
+
+    pub fn info(&self) -> &UsbScreenInfo{
+        &self.info
+    }
+
+    pub fn stats(&self) -> TransferStats{
+        self.stats.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+}
+
+// 启动热插拔监听线程(Linux上使用netlink)
+fn start_hotplug_watch() -> Option<HotplugState>{
+    let watch = match nusb::watch_devices(){
+        Ok(watch) => watch,
+        Err(err) => {
+            warn!("无法监听USB热插拔事件，改为定时查找:{err:?}");
+            return None;
+        }
+    };
+    let state = Arc::new((Mutex::new(0u64), Condvar::new()));
+    let thread_state = state.clone();
+    let ret = std::thread::Builder::new()
+        .name("usb-hotplug".to_string())
+        .spawn(move ||{
+            for event in futures_lite::stream::block_on(watch){
+                match event{
+                    HotplugEvent::Connected(d) => {
+                        info!("USB设备插入:{:?} {:?}", d.product_string(), d.serial_number());
+                    }
+                    HotplugEvent::Disconnected(id) => {
+                        info!("USB设备拔出:{id:?}");
+                        // 拔出的串口再次出现时需要重新探测
+                        #[cfg(feature = "usb-serial")]
+                        prune_not_screen_ports();
+                    }
+                }
+                let (generation, cvar) = &*thread_state;
+                if let Ok(mut generation) = generation.lock(){
+                    *generation += 1;
+                    cvar.notify_all();
+                }
+            }
+        });
+    if let Err(err) = ret{
+        warn!("启动USB热插拔监听线程失败:{err:?}");
+        return None;
+    }
+    Some(state)
+}
+
+// 等待USB设备插入或拔出，用于没有找到屏幕时等待重试
+// 支持热插拔监听时收到事件后重新查找，否则定时查找
+// 在查找之前创建，查找期间插入的设备也会触发下一次查找
+pub struct DeviceWaiter{
+    generation: u64,
+    created: Instant,
+    // 收到热插拔事件的时间，等待设备节点创建后再查找
+    changed_at: Option<Instant>,
+}
+
+impl DeviceWaiter{
+    pub fn start() -> Self{
+        let generation = HOTPLUG.as_ref()
+            .and_then(|state| state.0.lock().ok().map(|g| *g))
+            .unwrap_or(0);
+        Self{ generation, created: Instant::now(), changed_at: None }
+    }
+
+    // 最多等待 WAIT_SLICE 后返回，返回true时需要重新查找设备
+    pub fn wait(&mut self) -> bool{
+        let deadline = match (self.changed_at, HOTPLUG.is_some()){
+            (Some(changed_at), _) => changed_at + HOTPLUG_SETTLE_DELAY,
+            (None, true) => self.created + HOTPLUG_RESCAN_INTERVAL,
+            (None, false) => self.created + POLL_INTERVAL,
+        };
+        let now = Instant::now();
+        if now >= deadline{
+            return true;
+        }
+        let timeout = (deadline - now).min(WAIT_SLICE);
+        let state = match HOTPLUG.as_ref(){
+            Some(state) if self.changed_at.is_none() => state,
+            _ => {
+                std::thread::sleep(timeout);
+                return false;
+            }
+        };
+        let (generation, cvar) = &**state;
+        let start = self.generation;
+        let changed = match generation.lock(){
+            Ok(guard) => match cvar.wait_timeout_while(guard, timeout, |g| *g == start){
+                Ok((guard, _)) => Some(*guard).filter(|g| *g != start),
+                Err(_) => None,
+            },
+            Err(_) => {
+                std::thread::sleep(timeout);
+                None
+            }
+        };
+        if let Some(changed) = changed{
+            self.generation = changed;
+            self.changed_at = Some(Instant::now());
+        }
+        false
+    }
+}
+
+// 删除已经不存在的串口的探测记录
+#[cfg(feature = "usb-serial")]
+fn prune_not_screen_ports(){
+    let ports: HashSet<String> = serialport::available_ports()
+        .unwrap_or(vec![])
+        .into_iter()
+        .map(|p| p.port_name)
+        .collect();
+    if let Ok(mut not_screen_ports) = NOT_SCREEN_PORTS.lock(){
+        not_screen_ports.retain(|port, _| ports.contains(port));
+    }
+}
+
+// 预留设备地址，find_and_open_a_screen 会跳过被预留的设备
+pub fn reserve_device_address(device_address: &str){
+    if let Ok(mut reserved) = RESERVED_DEVICES.lock(){
+        *reserved.entry(address_key(device_address)).or_insert(0) += 1;
+    }
+}
+
+// 取消预留(重新加载后screen文件绑定了其他设备)
+pub fn release_device_address(device_address: &str){
+    if let Ok(mut reserved) = RESERVED_DEVICES.lock(){
+        let key = address_key(device_address);
+        if let Some(count) = reserved.get_mut(&key){
+            *count -= 1;
+            if *count == 0{
+                reserved.remove(&key);
+            }
+        }
+    }
+}
+
+// 占用设备: 检查和记录在同一个锁内完成，两个屏幕线程不会同时打开同一个设备
+// bound 为 true 时可以占用被预留的设备
+fn claim_device(address: &str, bound: bool) -> bool{
+    let key = address_key(address);
+    let mut opened = match OPENED_DEVICES.lock(){
+        Ok(opened) => opened,
+        Err(_) => return false,
+    };
+    if opened.contains(&key){
+        return false;
+    }
+    if !bound && RESERVED_DEVICES.lock().map(|reserved| reserved.contains_key(&key)).unwrap_or(false){
+        return false;
+    }
+    opened.insert(key);
+    true
+}
+
+fn release_device(address: &str){
+    if let Ok(mut opened) = OPENED_DEVICES.lock(){
+        opened.remove(&address_key(address));
+    }
+}
+
 pub fn find_and_open_a_screen() -> Option<UsbScreen>{
     //先查找串口设备
     let devices = find_all_device();
     for info in devices{
-        if let Ok(screen) = UsbScreen::open(info){
+        // 已经打开或者被预留的设备在占用时跳过
+        if let Ok(screen) = UsbScreen::open_claimed(info, false){
             return Some(screen);
         }
     }
     None
 }
 
+// 打开指定地址的屏幕，USB Raw设备的地址是 device_address，USB串口设备的地址是串口名称
+// 指定了地址时只打开该设备，不会改用其他屏幕; 未指定地址时打开找到的第一个屏幕
+pub fn find_and_open_screen(device_address: Option<&str>) -> Option<UsbScreen>{
+    // 在第一次查找之前开始监听，避免错过查找期间插入的设备
+    Lazy::force(&HOTPLUG);
+    let device_address = match device_address{
+        Some(addr) => addr,
+        None => return find_and_open_a_screen(),
+    };
+    let info = find_all_device()
+        .into_iter()
+        .find(|info| is_same_address(&info.address, device_address));
+    match info{
+        Some(info) => match UsbScreen::open(info){
+            Ok(screen) => Some(screen),
+            Err(err) => {
+                warn!("屏幕打开失败:{device_address} {err:?}");
+                None
+            }
+        },
+        None => {
+            warn!("未找到指定地址的屏幕:{device_address}");
+            None
+        }
+    }
+}
+
+// Windows串口名称不区分大小写(COM3/com3)
+fn is_same_address(address: &str, device_address: &str) -> bool{
+    address_key(address) == address_key(device_address)
+}
+
+fn address_key(address: &str) -> String{
+    address.trim().to_lowercase()
+}
+
 pub fn open_usb_raw_device(device_address: &str) -> Result<Interface>{
     let di = nusb::list_devices()?;
     for d in di{
@@ -130,6 +776,11 @@ fn get_screen_size_from_serial_number(serial_number:&str) -> (u16, u16){
     (width, height)
 }
 
+// 串号中分号后面是固件声明的功能，例如 USBSCR320x240;CAPS
+fn serial_has_feature(serial_number:&str, feature:&str) -> bool{
+    serial_number.split(';').skip(1).any(|f| f.trim().eq_ignore_ascii_case(feature))
+}
+
 // 查询所有USB屏幕设备
 // 对于USB Raw返回的第2个参数是 device_address
 // 对于USB Serial, 返回的第2个参数是串口名称
@@ -150,6 +801,9 @@ pub fn find_all_device() -> Vec<UsbScreenInfo>{
                     width,
                     height,
                     is_esp32_wifi: false,
+                    supports_delta: false,
+                    query_caps: serial_has_feature(serial_number, "CAPS"),
+                    capabilities: None,
                 });
             }
         }
@@ -168,9 +822,9 @@ pub fn find_all_device() -> Vec<UsbScreenInfo>{
 }
 
 /// 通过发送ReadInfo命令探测串口是否是ESP32 WiFi屏幕
-/// 返回 Some((width, height)) 如果探测成功
+/// 返回 Some((width, height, features)) 如果探测成功
 #[cfg(feature = "usb-serial")]
-fn probe_port_with_readinfo(port_name: &str, timeout_ms: u64) -> Option<(u16, u16)> {
+fn probe_port_with_readinfo(port_name: &str, timeout_ms: u64) -> Option<ReadInfo> {
     let timeout = Duration::from_millis(timeout_ms);
     
     // 尝试打开串口
@@ -178,9 +832,21 @@ fn probe_port_with_readinfo(port_name: &str, timeout_ms: u64) -> Option<(u16, u1
         .timeout(Duration::from_millis(200))
         .open() {
         Ok(p) => p,
+        // 串口可能正在被占用，不记为探测失败
         Err(_) => return None,
     };
 
+    let ret = read_info(&mut port, timeout);
+    if ret.is_none(){
+        if let Ok(mut not_screen_ports) = NOT_SCREEN_PORTS.lock(){
+            *not_screen_ports.entry(port_name.to_string()).or_insert(0) += 1;
+        }
+    }
+    ret
+}
+
+#[cfg(feature = "usb-serial")]
+fn read_info(port: &mut Box<dyn SerialPort>, timeout: Duration) -> Option<ReadInfo> {
     // 清空缓冲区
     let mut drain_buf = [0u8; 1024];
     let _ = port.read(&mut drain_buf);
@@ -205,22 +871,27 @@ fn probe_port_with_readinfo(port_name: &str, timeout_ms: u64) -> Option<(u16, u1
                     let line = String::from_utf8_lossy(&buf[..pos]).to_string();
                     debug!("ReadInfo响应: {}", line);
                     
-                    // 解析响应: ESP32-WIFI-SCREEN;{width};{height};PROTO:USB-SCREEN
+                    // 解析响应: ESP32-WIFI-SCREEN;{width};{height};PROTO:USB-SCREEN[;FEAT:DELTA,BACKLIGHT,POWER]
                     if let Some(idx) = line.to_uppercase().find("ESP32-WIFI-SCREEN") {
                         let payload = &line[idx..];
                         if payload.contains("PROTO:USB-SCREEN") {
                             let parts: Vec<&str> = payload.split(';').collect();
+                            // 新固件在FEAT字段中声明支持的功能
+                            let features: Vec<String> = parts.iter()
+                                .filter_map(|p| p.trim().strip_prefix("FEAT:"))
+                                .flat_map(parse_list)
+                                .collect();
                             if parts.len() >= 4 {
                                 let w = parts.get(1).and_then(|s| s.parse::<u16>().ok());
                                 let h = parts.get(2).and_then(|s| s.parse::<u16>().ok());
                                 if let (Some(w), Some(h)) = (w, h) {
                                     if w > 0 && h > 0 {
-                                        return Some((w, h));
+                                        return Some((w, h, features));
                                     }
                                 }
                             }
                             // 格式不对但确实是ESP32屏幕，使用默认尺寸
-                            return Some((240, 240));
+                            return Some((240, 240, features));
                         }
                     }
                     break;
@@ -234,78 +905,107 @@ fn probe_port_with_readinfo(port_name: &str, timeout_ms: u64) -> Option<(u16, u1
     None
 }
 
+// 环境变量中设置的额外串口，和USB串口一样查找屏幕，用于串口屏幕模拟器(/dev/pts/N)等不是USB设备的串口
+// 多个串口用逗号分隔，串口名称后面可以加上 =串号 模拟老设备，例如 /dev/pts/3,/dev/pts/4=USBSCR160x128
+#[cfg(feature = "usb-serial")]
+const EXTRA_PORTS_ENV: &str = "USB_SCREEN_EXTRA_PORTS";
+
+#[cfg(feature = "usb-serial")]
+fn extra_serial_ports() -> Vec<(String, Option<String>)>{
+    let ports = match std::env::var(EXTRA_PORTS_ENV){
+        Ok(ports) => ports,
+        Err(_) => return vec![],
+    };
+    ports.split(',')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.split_once('='){
+            Some((name, serial_number)) => (name.trim().to_string(), Some(serial_number.trim().to_string())),
+            None => (p.to_string(), None),
+        })
+        // 串口已经不存在时跳过，和拔出的USB串口一样
+        .filter(|(name, _)| !std::path::Path::new(name).starts_with("/dev") || std::path::Path::new(name).exists())
+        .collect()
+}
+
 #[cfg(feature = "usb-serial")]
 pub fn find_usb_serial_device() -> Vec<UsbScreenInfo>{
     let ports: Vec<SerialPortInfo> = serialport::available_ports().unwrap_or(vec![]);
     let mut devices = vec![];
     // 记录已经通过串号识别的端口，避免重复探测
     let mut identified_ports: Vec<String> = vec![];
-    // 当前可用的USB串口名称列表
-    let available_usb_ports: Vec<String> = ports.iter()
-        .filter_map(|p| {
-            if let SerialPortType::UsbPort(_) = &p.port_type {
-                Some(p.port_name.clone())
-            } else {
-                None
-            }
-        })
-        .collect();
-    
-    // 第一步：通过串号识别传统的USBSCR设备
+    // 当前可用的USB串口: (串口名称, 串号)
+    let mut usb_ports: Vec<(String, Option<String>)> = vec![];
     for p in ports.iter() {
         #[cfg(not(windows))]
         info!("USB Serial 设备:{:?}", p);
-        match p.port_type.clone(){
-            SerialPortType::UsbPort(port) => {
-                let serial_number = port.serial_number.unwrap_or("".to_string());
-                if serial_number.starts_with("USBSCR"){
-                    let port_name = p.port_name.clone();
-                    let (width, height) = get_screen_size_from_serial_number(&serial_number);
-                    devices.push(UsbScreenInfo{
-                        label: format!("USB {}", &port_name), 
-                        address: port_name.clone(),
-                        width,
-                        height,
-                        is_esp32_wifi: false,
-                    });
-                    identified_ports.push(port_name);
-                }
-            }
-            _ => ()
+        if let SerialPortType::UsbPort(port) = &p.port_type {
+            usb_ports.push((p.port_name.clone(), port.serial_number.clone()));
+        }
+    }
+    usb_ports.extend(extra_serial_ports());
+    let available_usb_ports: Vec<String> = usb_ports.iter().map(|(name, _)| name.clone()).collect();
+    // 拔出的串口再次出现时重新探测
+    if let Ok(mut not_screen_ports) = NOT_SCREEN_PORTS.lock(){
+        not_screen_ports.retain(|port, _| available_usb_ports.contains(port));
+    }
+    
+    // 第一步：通过串号识别传统的USBSCR设备
+    for (port_name, serial_number) in usb_ports.iter() {
+        let serial_number = serial_number.as_deref().unwrap_or("");
+        if serial_number.starts_with("USBSCR"){
+            let (width, height) = get_screen_size_from_serial_number(serial_number);
+            devices.push(UsbScreenInfo{
+                label: format!("USB {}", port_name), 
+                address: port_name.clone(),
+                width,
+                height,
+                is_esp32_wifi: false,
+                supports_delta: false,
+                query_caps: false,
+                capabilities: None,
+            });
+            identified_ports.push(port_name.clone());
         }
     }
     
     // 第二步：对未识别的USB串口设备进行ReadInfo探测
-    for p in ports.iter() {
+    for port_name in available_usb_ports.iter() {
         // 跳过已识别的端口
-        if identified_ports.contains(&p.port_name) {
+        if identified_ports.contains(port_name) {
             continue;
         }
         
-        // 只探测USB类型的串口
-        if let SerialPortType::UsbPort(_) = &p.port_type {
-            debug!("尝试ReadInfo探测: {}", p.port_name);
-            if let Some((width, height)) = probe_port_with_readinfo(&p.port_name, 800) {
-                info!("通过ReadInfo发现ESP32 WiFi屏幕: {} ({}x{})", p.port_name, width, height);
-                // 缓存发现的设备信息
-                if let Ok(mut cache) = ESP32_DEVICE_CACHE.lock() {
-                    cache.insert(p.port_name.clone(), (width, height));
-                }
-                devices.push(UsbScreenInfo{
-                    label: format!("ESP32 {}", &p.port_name),
-                    address: p.port_name.clone(),
-                    width,
-                    height,
-                    is_esp32_wifi: true,
-                });
-                identified_ports.push(p.port_name.clone());
+        // 多次探测都没有响应的串口不是屏幕(ESP32刚上电时可能来不及响应，所以第一次失败后还会再试一次)
+        let failures = NOT_SCREEN_PORTS.lock().ok().and_then(|m| m.get(port_name).copied()).unwrap_or(0);
+        if failures >= MAX_PROBE_FAILURES {
+            continue;
+        }
+
+        debug!("尝试ReadInfo探测: {}", port_name);
+        if let Some((width, height, features)) = probe_port_with_readinfo(port_name, 800) {
+            info!("通过ReadInfo发现ESP32 WiFi屏幕: {} ({}x{}) 功能:{:?}", port_name, width, height, features);
+            // 缓存发现的设备信息
+            if let Ok(mut cache) = ESP32_DEVICE_CACHE.lock() {
+                cache.insert(port_name.clone(), (width, height, features.clone()));
             }
+            devices.push(UsbScreenInfo{
+                label: format!("ESP32 {}", port_name),
+                address: port_name.clone(),
+                width,
+                height,
+                is_esp32_wifi: true,
+                supports_delta: features.iter().any(|f| f == "DELTA"),
+                query_caps: false,
+                capabilities: Some(ScreenCapabilities::with_features(&features)),
+            });
+            identified_ports.push(port_name.clone());
         }
     }
     
     // 第三步：从缓存中恢复已发现但当前无法探测的ESP32设备（可能正在被使用）
     if let Ok(cache) = ESP32_DEVICE_CACHE.lock() {
-        for (port_name, (width, height)) in cache.iter() {
+        for (port_name, (width, height, features)) in cache.iter() {
             // 如果端口仍在系统中可见，但未被探测到（可能被占用），从缓存恢复
             if available_usb_ports.contains(port_name) && !identified_ports.contains(port_name) {
                 debug!("从缓存恢复ESP32设备: {} ({}x{})", port_name, width, height);
@@ -315,6 +1015,9 @@ pub fn find_usb_serial_device() -> Vec<UsbScreenInfo>{
                     width: *width,
                     height: *height,
                     is_esp32_wifi: true,
+                    supports_delta: features.iter().any(|f| f == "DELTA"),
+                    query_caps: false,
+                    capabilities: Some(ScreenCapabilities::with_features(features)),
                 });
             }
         }
@@ -341,18 +1044,62 @@ pub fn clear_screen_serial(color: Rgb<u8>, port:&mut dyn SerialPort, width: u16,
 }
 
 pub fn draw_rgb_image(x: u16, y: u16, img:&RgbImage, interface:&Interface) -> anyhow::Result<()>{
+    draw_rgb_image_with_limit(x, y, img, MAX_USB_RAW_PAYLOAD, interface)
+}
+
+fn draw_rgb_image_with_limit(x: u16, y: u16, img:&RgbImage, max_payload: usize, interface:&Interface) -> anyhow::Result<()>{
     //ST7789驱动使用的是Big-Endian
     let rgb565 = rgb888_to_rgb565_be(&img, img.width() as usize, img.height() as usize);
-    draw_rgb565(&rgb565, x, y, img.width() as u16, img.height() as u16, interface)
+    draw_rgb565_with_limit(&rgb565, x, y, img.width() as u16, img.height() as u16, max_payload, interface)
 }
 
-pub fn draw_rgb565(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16, interface:&Interface) -> anyhow::Result<()>{
+// max_payload: 屏幕一次能接收的最大数据量，超过时切分成条带
+fn draw_rgb565_with_limit(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16, max_payload: usize, interface:&Interface) -> anyhow::Result<()>{
     // info!("压缩前大小:{}", rgb565.len());
     let rgb565_u8_slice = lz4_flex::compress_prepend_size(rgb565);
     // info!("压缩后大小:{}", rgb565_u8_slice.len());
-    if rgb565_u8_slice.len() >1024*28 {
+    if rgb565_u8_slice.len() <= max_payload {
+        return send_rgb565_compressed(rgb565_u8_slice, x, y, width, height, interface);
+    }
+    if height <= 1 {
         return Err(anyhow!("图像太大了!"));
     }
+    // 图像太大时按照压缩后的大小估算，切分成多个水平条带分别发送
+    let strips = (rgb565_u8_slice.len() / max_payload + 1).min(height as usize) as u16;
+    let strip_height = height.div_ceil(strips);
+    debug!("图像压缩后{}字节，分成{}个条带发送", rgb565_u8_slice.len(), strips);
+    let row_bytes = width as usize * 2;
+    let mut top = 0;
+    while top < height {
+        let h = strip_height.min(height - top);
+        let strip = &rgb565[top as usize * row_bytes..(top + h) as usize * row_bytes];
+        // 估算不准时条带还会继续切分
+        draw_rgb565_with_limit(strip, x, y + top, width, h, max_payload, interface)?;
+        top += h;
+    }
+    Ok(())
+}
+
+// 查询屏幕能力: 发送 readinfo，从 BULK_IN_EP 读取一行响应
+// 只能发送给在串号中声明了 CAPS 的设备，老固件不认识 readinfo
+pub fn query_capabilities(interface:&Interface) -> Option<ScreenCapabilities>{
+    const READ_INFO:u64 = u64::from_be_bytes(*b"readinfo");
+    block_on(async {
+        async_std::future::timeout(Duration::from_millis(100), interface.bulk_out(BULK_OUT_EP, READ_INFO.to_be_bytes().into()))
+            .await
+    }).ok()?.status.ok()?;
+    let result = block_on(async {
+        async_std::future::timeout(Duration::from_millis(300), interface.bulk_in(BULK_IN_EP, RequestBuffer::new(256)))
+            .await
+    }).ok()?;
+    result.status.ok()?;
+    let line = String::from_utf8_lossy(&result.data).to_string();
+    debug!("能力查询响应: {}", line);
+    ScreenCapabilities::parse(line.lines().next().unwrap_or(""))
+}
+
+// 发送一个已经压缩好的图像: 帧头 + lz4数据 + 帧尾
+fn send_rgb565_compressed(rgb565_u8_slice: Vec<u8>, x: u16, y: u16, width: u16, height: u16, interface:&Interface) -> anyhow::Result<()>{
     const IMAGE_AA:u64 = 7596835243154170209;
     const BOOT_USB:u64 = 7093010483740242786;
     const IMAGE_BB:u64 = 7596835243154170466;
@@ -452,6 +1199,27 @@ pub fn draw_rgb565_serial(rgb565:&[u8], x: u16, y: u16, width: u16, height: u16,
     Ok(())
 }
 
+// 支持差分帧的ESP32固件: 帧头和帧尾不变，中间的数据是WiFi协议的帧
+// (wflz4ke_/wflz4dl_/wflz4no_ + WIDTH + HEIGHT + LZ4数据)，固件根据magic区分
+#[cfg(feature = "usb-serial")]
+pub fn draw_delta_frame_serial(wifi_frame:&[u8], x: u16, y: u16, width: u16, height: u16, port:&mut dyn SerialPort) -> anyhow::Result<()>{
+    const IMAGE_AA:u64 = 7596835243154170209;
+    const IMAGE_BB:u64 = 7596835243154170466;
+
+    let mut frame = Vec::with_capacity(16 + wifi_frame.len() + 8);
+    frame.extend_from_slice(&IMAGE_AA.to_be_bytes());
+    frame.extend_from_slice(&width.to_be_bytes());
+    frame.extend_from_slice(&height.to_be_bytes());
+    frame.extend_from_slice(&x.to_be_bytes());
+    frame.extend_from_slice(&y.to_be_bytes());
+    frame.extend_from_slice(wifi_frame);
+    frame.extend_from_slice(&IMAGE_BB.to_be_bytes());
+
+    port.write_all(&frame)?;
+    port.flush()?;
+    Ok(())
+}
+
 #[cfg(not(windows))]
 fn list_acm_devices() -> Vec<String> {
     let dir_path = std::path::Path::new("/dev");
@@ -475,4 +1243,29 @@ fn list_acm_devices() -> Vec<String> {
             None
         })
     }).collect()
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_parse_capabilities() {
+    let caps = ScreenCapabilities::parse("USB-SCREEN;VER:1.3.0;PANEL:ST7789;SIZE:320x240;MAX:61440;CODEC:lz4,delta\0\0").unwrap();
+    assert_eq!(caps.firmware_version.as_deref(), Some("1.3.0"));
+    assert_eq!(caps.panel_model.as_deref(), Some("ST7789"));
+    assert_eq!(caps.size, Some((320, 240)));
+    assert_eq!(caps.max_payload, 61440);
+    assert!(caps.supports("DELTA"));
+    assert!(!caps.supports(FEAT_POWER));
+
+    // 缺少的字段使用默认值
+    let caps = ScreenCapabilities::parse("USB-SCREEN;VER:1.0").unwrap();
+    assert_eq!(caps.max_payload, MAX_USB_RAW_PAYLOAD);
+    assert_eq!(caps.codecs, vec!["LZ4".to_string()]);
+    assert!(!caps.supports(FEAT_BACKLIGHT));
+
+    let caps = ScreenCapabilities::parse("USB-SCREEN;FEAT:backlight, power").unwrap();
+    assert!(caps.supports(FEAT_BACKLIGHT) && caps.supports(FEAT_POWER));
+
+    assert!(ScreenCapabilities::parse("ESP32-WIFI-SCREEN;240;240").is_none());
+
+    assert!(serial_has_feature("USBSCR320x240;CAPS", "CAPS"));
+    assert!(!serial_has_feature("USBSCR320x240", "CAPS"));
+}
@@ -1,5 +1,6 @@
 use crate::{
-    monitor::{self, system_uptime, webcam_frame},
+    data_source::{self, SourceContext, ValueFormat},
+    monitor::{self, webcam_frame},
     nmc::ICONS,
     utils::{degrees_to_radians, execute_user_command, resize_image, test_resize_image},
 };
@@ -15,19 +16,6 @@ use uuid::Uuid;
 
 static DEFAULT_IMAGE: &[u8] = include_bytes!("../images/icon_photo.png");
 
-fn indexed_text_or_first<F>(index: usize, getter: F) -> Option<String>
-where
-    F: Fn(usize) -> Option<String>,
-{
-    getter(index).or_else(|| {
-        if index == 0 {
-            None
-        } else {
-            getter(0)
-        }
-    })
-}
-
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Rect {
     pub left: i32,
@@ -131,7 +119,8 @@ impl Rect {
     }
 }
 
-pub trait Widget {
+// 需要Send: 多个屏幕时每个屏幕在各自的线程中渲染
+pub trait Widget: Send {
     fn draw(&mut self, context: &mut OffscreenCanvas);
     fn id(&self) -> &str;
     fn index(&self) -> usize;
@@ -141,6 +130,10 @@ pub trait Widget {
     fn position(&self) -> &Rect;
     fn position_mut(&mut self) -> &mut Rect;
     fn type_name(&self) -> &str;
+    // 组件使用的数据源, 默认和 type_name 相同
+    fn data_source(&self) -> &str{
+        self.type_name()
+    }
     fn as_any_mut(&mut self) -> &mut dyn Any;
     fn is_text(&self) -> bool{
         self.type_name() != "images" && self.type_name() != "webcam"
@@ -191,7 +184,10 @@ pub struct TextWidget {
     pub custom_script: Option<String>,
     //这是执行命令完成后获得的数据
     #[serde(skip_serializing, skip_deserializing)]
-    pub custom_script_data: Arc<Mutex<CustomScriptStatus>>
+    pub custom_script_data: Arc<Mutex<CustomScriptStatus>>,
+    // 数值格式: 小数位数、单位、百分比或数值
+    #[serde(default)]
+    pub format: ValueFormat,
 }
 
 impl TextWidget {
@@ -222,7 +218,8 @@ impl TextWidget {
             width: None,
             height: None,
             custom_script: None,
-            custom_script_data: Arc::new(Mutex::new(CustomScriptStatus{ loading: false, result: String::new()}))
+            custom_script_data: Arc::new(Mutex::new(CustomScriptStatus{ loading: false, result: String::new()})),
+            format: ValueFormat::default(),
         }
     }
 
@@ -263,6 +260,8 @@ impl TextWidget {
 impl Widget for TextWidget {
     fn draw(&mut self, context: &mut OffscreenCanvas) {
         
+        // 进度条显示时使用的百分比
+        let mut percent = None;
         let mut custom_script = None;
         if let Some(script) = self.custom_script.as_ref(){
             if script.trim().len() > 0{
@@ -279,114 +278,15 @@ impl Widget for TextWidget {
             }
         }else{
             if self.type_name != "text" {
-                if let Some(text) = match self.type_name.as_str() {
-                    "cpu" => monitor::cpu_brand(),
-                    "memory" => monitor::memory_info(),
-                    "memory_total" => monitor::memory_total(),
-                    "memory_percent" => monitor::memory_percent(),
-                    "swap" => monitor::swap_info(),
-                    "swap_percent" => monitor::swap_percent(),
-                    "system" => monitor::system_name(),
-                    "version" => monitor::os_version(),
-                    "kernel" => monitor::kernel_version(),
-                    "host" => monitor::host_name(),
-                    "cpu_freq" => monitor::cpu_clock_speed(None),
-                    "cpu_usage" => {
-                        if self.num_widget == 1 {
-                            monitor::cpu_usage()
-                        } else {
-                            monitor::cpu_usage_percpu(self.num_widget_index)
-                        }
-                    }
-                    "cpu_temp." => {
-                        Some(monitor::cpu_temperature().unwrap_or(monitor::EMPTY_STRING.to_string()))
-                    }
-                    "cpu_cores_power" => {
-                        Some(monitor::cpu_cores_power().unwrap_or(monitor::EMPTY_STRING.to_string()))
-                    }
-                    "cpu_package_power" => {
-                        Some(monitor::cpu_package_power().unwrap_or(monitor::EMPTY_STRING.to_string()))
-                    }
-                    "cpu_fan" => Some(monitor::cpu_fan().unwrap_or(monitor::EMPTY_STRING.to_string())),
-                    "gpu_fan" => Some(
-                        indexed_text_or_first(self.num_widget_index, monitor::gpu_fan)
-                            .unwrap_or(monitor::EMPTY_STRING.to_string()),
-                    ),
-                    "gpu_clock" => Some(
-                        indexed_text_or_first(self.num_widget_index, monitor::gpu_clocks)
-                            .unwrap_or(monitor::EMPTY_STRING.to_string()),
-                    ),
-                    "gpu_load" => Some(
-                        indexed_text_or_first(self.num_widget_index, monitor::gpu_load)
-                            .unwrap_or(monitor::EMPTY_STRING.to_string()),
-                    ),
-                    "gpu_memory_load" => Some(
-                        indexed_text_or_first(self.num_widget_index, monitor::gpu_memory_load)
-                            .unwrap_or(monitor::EMPTY_STRING.to_string()),
-                    ),
-                    "gpu_memory_total_mb" => Some(
-                        indexed_text_or_first(self.num_widget_index, monitor::gpu_memory_total_mb)
-                            .unwrap_or(monitor::EMPTY_STRING.to_string()),
-                    ),
-                    "gpu_memory_total_gb" => Some(
-                        indexed_text_or_first(self.num_widget_index, monitor::gpu_memory_total_gb)
-                            .unwrap_or(monitor::EMPTY_STRING.to_string()),
-                    ),
-                    "gpu_temp." => Some(
-                        indexed_text_or_first(self.num_widget_index, monitor::gpu_temperature)
-                            .unwrap_or(monitor::EMPTY_STRING.to_string()),
-                    ),
-                    "gpu_cores_power" => {
-                        Some(monitor::gpu_cores_power().unwrap_or(monitor::EMPTY_STRING.to_string()))
-                    }
-                    "gpu_package_power" => {
-                        Some(monitor::gpu_package_power().unwrap_or(monitor::EMPTY_STRING.to_string()))
-                    }
-                    "num_cpu" => monitor::num_cpus(),
-                    "num_process" => monitor::num_process(),
-                    "disk_usage" => indexed_text_or_first(self.num_widget_index, monitor::disk_usage),
-                    "date" => Some(monitor::date()),
-                    "local_ip" => monitor::local_ip_addresses(),
-                    "net_ip" => monitor::net_ip_address(),
-                    "net_ip_info" => monitor::net_ip_info(),
-                    "time" => Some(monitor::time()),
-                    "weekday" => Some(monitor::chinese_weekday()),
-                    "lunar_year" => Some(monitor::lunar_year()),
-                    "lunar_date" => Some(monitor::lunar_date()),
-                    "weather" => match monitor::weather_info() {
-                        None => Some(monitor::EMPTY_STRING.to_string()),
-                        Some(w) => {
-                            match self.tag1.as_str() {
-                                "1" => Some(format!("{}", w.station.city)),         //城市
-                                "2" => Some(format!("{}℃", w.weather.temperature)), //气温
-                                "3" => Some(format!("{}℃", w.wind.direct)),         //风向
-                                "4" => Some(format!("{}", w.wind.power)),           //风力
-                                "5" => Some(format!("{}级", w.wind.speed)),         //风级
-                                "6" => Some(format!("{}", w.weather.img)),          //图标
-                                _ => Some(format!("{}", w.weather.info)),
-                            }
-                        }
-                    },
-                    "uptime" => {
-                        let uptime = system_uptime();
-                        let uptime_str = match self.tag1.as_str() {
-                            //运行分钟数
-                            "1" => Some(format!("{}", uptime.minutes)),
-                            //运行小时数
-                            "2" => Some(format!("{}", uptime.hours)),
-                            //运行天数
-                            "3" => Some(format!("{}", uptime.days)),
-                            //运行秒数
-                            _ => Some(format!("{}", uptime.seconds)),
-                        };
-                        uptime_str
-                    },
-                    "disk_read_speed" => monitor::disk_speed_per_sec().map(|(r, _w)| r),
-                    "disk_write_speed" => monitor::disk_speed_per_sec().map(|(_r, w)| w),
-                    "received_speed" => monitor::network_speed_per_sec().map(|(r, _t)| r),
-                    "transmitted_speed" => monitor::network_speed_per_sec().map(|(_r, t)| t),
-                    _ => None,
-                } {
+                let ctx = SourceContext {
+                    num_widget_index: self.num_widget_index,
+                    num_widget: self.num_widget,
+                    tag1: &self.tag1,
+                };
+                let format = self.format.or(&data_source::default_format(&self.type_name));
+                let value = data_source::value(&self.type_name, &ctx);
+                percent = value.as_ref().map(|v| v.percent());
+                if let Some(text) = value.map(|v| v.format(&format)) {
                     if self.text != text && text != monitor::EMPTY_STRING {
                         self.text = text;
                     }
@@ -408,12 +308,13 @@ impl Widget for TextWidget {
             context.draw_image_at(&ICONS[img_idx], x, y, Some(o), None);
         } else if self.type_name != "weather" && self.type_name != "uptime" && (self.tag1 == "1" || self.tag1 == "2") {
             //是否渲染成进度条
-            let percent = self
-                .text
-                .replace("%", "")
-                .replace("°C", "")
-                .parse::<f32>()
-                .unwrap_or(0.);
+            let percent = percent.unwrap_or_else(|| {
+                self.text
+                    .replace("%", "")
+                    .replace("°C", "")
+                    .parse::<f32>()
+                    .unwrap_or(0.)
+            });
 
             let width = self.width.unwrap_or(self.font_size as i32 * 5);
             let height = self.height.unwrap_or(self.font_size as i32);
@@ -874,27 +775,13 @@ impl ProgressWidget {
 
     /// 获取进度值
     fn get_percent(&self) -> f32 {
-        let text = match self.type_name.as_str() {
-            "cpu_usage" => {
-                if self.num_widget == 1 {
-                    monitor::cpu_usage()
-                } else {
-                    monitor::cpu_usage_percpu(self.num_widget_index)
-                }
-            }
-            "memory_percent" => monitor::memory_percent(),
-            "swap_percent" => monitor::swap_percent(),
-            "gpu_load" => indexed_text_or_first(self.num_widget_index, monitor::gpu_load),
-            "gpu_memory_load" => indexed_text_or_first(self.num_widget_index, monitor::gpu_memory_load),
-            "cpu_temp." => monitor::cpu_temperature(),
-            "gpu_temp." => indexed_text_or_first(self.num_widget_index, monitor::gpu_temperature),
-            "disk_usage" => indexed_text_or_first(self.num_widget_index, monitor::disk_usage),
-            _ => None,
+        let ctx = SourceContext {
+            num_widget_index: self.num_widget_index,
+            num_widget: self.num_widget,
+            tag1: "",
         };
-        text.unwrap_or_default()
-            .replace("%", "")
-            .replace("°C", "")
-            .parse::<f32>()
+        data_source::value(&self.type_name, &ctx)
+            .map(|v| v.percent())
             .unwrap_or(0.0)
     }
 }
@@ -966,11 +853,317 @@ impl Widget for ProgressWidget {
     fn get_label(&self) -> &str { "进度条" }
 }
 
+/// 图表类型
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub enum ChartType {
+    #[default]
+    Line,   // 折线图
+    Area,   // 面积图
+}
+
+#[cfg(feature = "editor")]
+pub const CHART_TYPE_NAME: &str = "line_chart";
+
+/// 历史数据图表组件, 数据来自 monitor::history
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ChartWidget {
+    pub id: String,
+    pub position: Rect,
+    pub type_name: String,           // 组件类型, 固定为 line_chart
+    pub source: String,              // 数据源: 记录了历史数据的指标, 见 monitor::history
+    pub chart_type: ChartType,
+    pub window_secs: u32,            // 显示最近多少秒的数据
+
+    // 纵轴范围, 未设置时按照数据自动缩放
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+
+    // 颜色配置
+    pub line_color: [u8; 4],         // 线条颜色
+    pub fill_color: [u8; 4],         // 面积图填充色
+    pub background_color: [u8; 4],   // 背景色, 透明时不绘制
+    pub line_width: f32,
+
+    // 运行时数据
+    pub num_widget_index: usize,
+    pub num_widget: usize,
+}
+
+impl ChartWidget {
+    // 编辑器中添加图表时调用
+    #[cfg(feature = "editor")]
+    pub fn new(x: i32, y: i32, source: &str) -> Self {
+        let (width, height) = (100, 40);
+        let mut chart = Self {
+            id: Uuid::new_v4().to_string(),
+            position: Rect::from(x - width / 2, y - height / 2, width, height),
+            type_name: CHART_TYPE_NAME.to_string(),
+            source: source.to_string(),
+            chart_type: ChartType::Line,
+            window_secs: 60,
+            min: None,
+            max: None,
+            line_color: [0, 200, 100, 255],
+            fill_color: [0, 200, 100, 80],
+            background_color: [0, 0, 0, 0],
+            line_width: 1.5,
+            num_widget_index: 0,
+            num_widget: 1,
+        };
+        chart.set_default_range();
+        chart
+    }
+
+    /// 百分比数据默认使用 0~100, 其他数据自动缩放
+    #[cfg(feature = "editor")]
+    pub fn set_default_range(&mut self) {
+        if ["cpu_usage", "memory_percent", "swap_percent"].contains(&self.source.as_str()) {
+            self.min = Some(0.);
+            self.max = Some(100.);
+        } else {
+            self.min = None;
+            self.max = None;
+        }
+    }
+
+    /// 计算纵轴范围, 自动缩放时数据全部非负则从0开始
+    fn value_range(&self, values: &[f64]) -> (f64, f64) {
+        let data_min = values.iter().copied().fold(f64::MAX, f64::min);
+        let data_max = values.iter().copied().fold(f64::MIN, f64::max);
+        let min = self.min.map(|v| v as f64).unwrap_or(data_min.min(0.));
+        let max = self.max.map(|v| v as f64).unwrap_or(data_max);
+        if max > min { (min, max) } else { (min, min + 1.) }
+    }
+}
+
+impl Widget for ChartWidget {
+    fn draw(&mut self, context: &mut OffscreenCanvas) {
+        let left = self.position.left as f32;
+        let bottom = self.position.bottom as f32;
+        let width = self.position.width() as f32;
+        let height = self.position.height() as f32;
+
+        if self.background_color[3] > 0 {
+            let bg_rect = CanvasRect::from(
+                self.position.left, self.position.top,
+                self.position.width(), self.position.height(),
+            );
+            context.fill_rect(bg_rect, Rgba(self.background_color));
+        }
+
+        let window = self.window_secs.max(1);
+        let history = monitor::history(&self.source, window);
+        if history.is_empty() {
+            return;
+        }
+        let values: Vec<f64> = history.iter().map(|(_, v)| *v).collect();
+        let (min, max) = self.value_range(&values);
+
+        // 最新的数据在最右边
+        let mut points: Vec<(f32, f32)> = history
+            .iter()
+            .map(|(age, value)| {
+                let x = left + width * (1. - (*age / window as f64) as f32);
+                let ratio = ((value - min) / (max - min)).clamp(0., 1.) as f32;
+                (x, bottom - height * ratio)
+            })
+            .collect();
+        // 只有一个采样时画一条水平线
+        if points.len() == 1 {
+            points.insert(0, (left, points[0].1));
+        }
+
+        if self.chart_type == ChartType::Area {
+            let mut polygon = points.clone();
+            polygon.push((points[points.len() - 1].0, bottom));
+            polygon.push((points[0].0, bottom));
+            context.fill_polygon(&polygon, Rgba(self.fill_color));
+        }
+        context.stroke_polyline(&points, self.line_width, Rgba(self.line_color));
+    }
+
+    fn id(&self) -> &str { &self.id }
+    fn position(&self) -> &Rect { &self.position }
+    fn position_mut(&mut self) -> &mut Rect { &mut self.position }
+    fn type_name(&self) -> &str { &self.type_name }
+    fn data_source(&self) -> &str { &self.source }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+    fn index(&self) -> usize { self.num_widget_index }
+    fn set_index(&mut self, idx: usize) { self.num_widget_index = idx; }
+    fn num_widget(&self) -> usize { self.num_widget }
+    fn set_num_widget(&mut self, num: usize) { self.num_widget = num; }
+
+    fn is_text(&self) -> bool { false }
+    fn is_image(&self) -> bool { false }
+    fn get_label(&self) -> &str { "图表" }
+}
+
+/// 多核心显示方式
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub enum CoresLayout {
+    #[default]
+    Bars,   // 柱状图
+    Grid,   // 热力图: 每个核心一个格子, 颜色随使用率变化
+}
+
+/// 柱状图方向
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub enum Orientation {
+    #[default]
+    Vertical,    // 柱子竖直, 从下往上增长
+    Horizontal,  // 柱子水平, 从左往右增长
+}
+
+/// CPU所有核心的使用率, 一个组件显示全部核心
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CpuCoresWidget {
+    pub id: String,
+    pub position: Rect,
+    pub type_name: String,           // 数据源: cpu_cores
+    pub layout: CoresLayout,
+    pub orientation: Orientation,
+    pub columns: usize,              // 热力图列数, 0为自动
+    pub gap: i32,                    // 核心之间的间隔
+
+    // 颜色配置, 热力图按照使用率在背景色和前景色之间渐变
+    pub foreground_color: [u8; 4],
+    pub background_color: [u8; 4],
+
+    // 运行时数据
+    pub num_widget_index: usize,
+    pub num_widget: usize,
+}
+
+impl CpuCoresWidget {
+    // 编辑器中添加组件时调用
+    #[cfg(feature = "editor")]
+    pub fn new(x: i32, y: i32) -> Self {
+        let (width, height) = (100, 40);
+        Self {
+            id: Uuid::new_v4().to_string(),
+            position: Rect::from(x - width / 2, y - height / 2, width, height),
+            type_name: "cpu_cores".to_string(),
+            layout: CoresLayout::Bars,
+            orientation: Orientation::Vertical,
+            columns: 0,
+            gap: 1,
+            foreground_color: [0, 200, 100, 255],
+            background_color: [60, 60, 60, 255],
+            num_widget_index: 0,
+            num_widget: 1,
+        }
+    }
+
+    fn draw_bars(&self, context: &mut OffscreenCanvas, cores: &[f32]) {
+        let vertical = self.orientation == Orientation::Vertical;
+        let (length, thickness) = if vertical {
+            (self.position.height(), self.position.width())
+        } else {
+            (self.position.width(), self.position.height())
+        };
+        let n = cores.len() as i32;
+        let slot = (thickness - self.gap * (n - 1)).max(n) as f32 / n as f32;
+        for (i, usage) in cores.iter().enumerate() {
+            let start = (i as f32 * (slot + self.gap as f32)).round() as i32;
+            // 组件太窄放不下所有核心时，不绘制超出组件的部分
+            if start >= thickness {
+                break;
+            }
+            let end = ((i as f32 * (slot + self.gap as f32) + slot).round() as i32).min(thickness);
+            let size = (end - start).max(1);
+            let value = ((length as f32) * (usage.clamp(0., 100.) / 100.)).round() as i32;
+            let (bg_rect, fg_rect) = if vertical {
+                let left = self.position.left + start;
+                (
+                    CanvasRect::from(left, self.position.top, size, length),
+                    CanvasRect::from(left, self.position.bottom - value, size, value),
+                )
+            } else {
+                let top = self.position.top + start;
+                (
+                    CanvasRect::from(self.position.left, top, length, size),
+                    CanvasRect::from(self.position.left, top, value, size),
+                )
+            };
+            context.fill_rect(bg_rect, Rgba(self.background_color));
+            if value > 0 {
+                context.fill_rect(fg_rect, Rgba(self.foreground_color));
+            }
+        }
+    }
+
+    fn draw_grid(&self, context: &mut OffscreenCanvas, cores: &[f32]) {
+        let n = cores.len();
+        let (width, height) = (self.position.width(), self.position.height());
+        // 自动列数: 让格子尽量接近正方形
+        let columns = if self.columns > 0 {
+            self.columns.min(n)
+        } else {
+            ((n as f32 * width as f32 / height.max(1) as f32).sqrt().ceil() as usize).clamp(1, n)
+        };
+        let rows = n.div_ceil(columns);
+        let cell_w = (width - self.gap * (columns as i32 - 1)) as f32 / columns as f32;
+        let cell_h = (height - self.gap * (rows as i32 - 1)) as f32 / rows as f32;
+        for (i, usage) in cores.iter().enumerate() {
+            let (col, row) = ((i % columns) as f32, (i / columns) as f32);
+            let left = (col * (cell_w + self.gap as f32)).round() as i32;
+            let top = (row * (cell_h + self.gap as f32)).round() as i32;
+            if left >= width || top >= height {
+                continue;
+            }
+            let right = ((col * (cell_w + self.gap as f32) + cell_w).round() as i32).min(width);
+            let bottom = ((row * (cell_h + self.gap as f32) + cell_h).round() as i32).min(height);
+            let rect = CanvasRect::from(
+                self.position.left + left,
+                self.position.top + top,
+                (right - left).max(1),
+                (bottom - top).max(1),
+            );
+            let t = usage.clamp(0., 100.) / 100.;
+            let mut color = [0u8; 4];
+            for (c, channel) in color.iter_mut().enumerate() {
+                let (from, to) = (self.background_color[c] as f32, self.foreground_color[c] as f32);
+                *channel = (from + (to - from) * t).round() as u8;
+            }
+            context.fill_rect(rect, Rgba(color));
+        }
+    }
+}
+
+impl Widget for CpuCoresWidget {
+    fn draw(&mut self, context: &mut OffscreenCanvas) {
+        let cores = monitor::cpu_usage_cores();
+        if cores.is_empty() {
+            return;
+        }
+        match self.layout {
+            CoresLayout::Bars => self.draw_bars(context, &cores),
+            CoresLayout::Grid => self.draw_grid(context, &cores),
+        }
+    }
+
+    fn id(&self) -> &str { &self.id }
+    fn position(&self) -> &Rect { &self.position }
+    fn position_mut(&mut self) -> &mut Rect { &mut self.position }
+    fn type_name(&self) -> &str { &self.type_name }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+    fn index(&self) -> usize { self.num_widget_index }
+    fn set_index(&mut self, idx: usize) { self.num_widget_index = idx; }
+    fn num_widget(&self) -> usize { self.num_widget }
+    fn set_num_widget(&mut self, num: usize) { self.num_widget = num; }
+
+    fn is_text(&self) -> bool { false }
+    fn is_image(&self) -> bool { false }
+    fn get_label(&self) -> &str { "CPU核心" }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub enum SaveableWidget {
     TextWidget(TextWidget),
     ImageWidget(ImageWidget),
     ProgressWidget(ProgressWidget),
+    ChartWidget(ChartWidget),
+    CpuCoresWidget(CpuCoresWidget),
 }
 
 //老版本
@@ -1082,4 +1275,4 @@ pub mod v10{
             self.num_widget = num;
         }
     }
-}
\ No newline at end of file
+}
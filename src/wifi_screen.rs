@@ -1,134 +1,303 @@
-use std::{net::TcpStream, sync::Mutex, time::{Duration, Instant}};
+use std::{collections::HashMap, net::{IpAddr, TcpStream, UdpSocket}, sync::{Arc, Mutex}, time::{Duration, Instant}};
 
 use crossbeam_channel::{bounded, Receiver, Sender};
-use fast_image_resize::{images::Image, Resizer};
-use image::{buffer::ConvertBuffer, RgbImage, RgbaImage};
-use log::info;
-use once_cell::sync::Lazy;
+use fast_image_resize::{images::{Image, ImageRef}, PixelType, ResizeAlg, ResizeOptions, Resizer};
+use image::{buffer::ConvertBuffer, codecs::jpeg::JpegEncoder, ExtendedColorType, Rgb, RgbImage, RgbaImage};
+use log::{info, warn};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use tungstenite::{connect, stream::MaybeTlsStream, WebSocket};
+use tungstenite::{stream::MaybeTlsStream, WebSocket};
 
-use crate::rgb565::rgb888_to_rgb565_be;
+use crate::{rgb565::{rgb565_be_to_rgb888, rgb888_to_rgb565_be}, wifi_security::{AuthError, WifiSecurity}};
 
 // WiFi帧差分协议 Magic Numbers (8字节)
 // 格式: MAGIC(8) + WIDTH(2) + HEIGHT(2) + LZ4_COMPRESSED_DATA
 const WIFI_KEY_MAGIC: &[u8; 8] = b"wflz4ke_"; // lz4压缩的关键帧(完整RGB565)
 const WIFI_DLT_MAGIC: &[u8; 8] = b"wflz4dl_"; // lz4压缩的差分帧(XOR差分数据)
 const WIFI_NOP_MAGIC: &[u8; 8] = b"wflz4no_"; // 无变化帧(屏幕静止，跳过绘制)
+// 以下编码只在固件的 /display_config 中声明了 codecs 时使用
+// 分块差分帧(TILE): 数据为 LZ4(TILE_SIZE(2) + COUNT(2) + COUNT个分块位置X(2)Y(2) + 各分块的RGB565数据)
+// 只包含变化的分块，分块像素直接覆盖屏幕内容(不是XOR)，右边和下边的分块按屏幕边界裁剪
+const WIFI_TILE_MAGIC: &[u8; 8] = b"wflz4tl_";
+// JPEG关键帧(JPEG): 数据为 baseline JPEG，有损，解码后作为参考帧
+const WIFI_JPEG_MAGIC: &[u8; 8] = b"wfjpgke_";
+
+// 局域网发现: 向 DISCOVERY_PORT 广播 DISCOVERY_REQUEST，固件回复
+// ESP32-WIFI-SCREEN;ID:{设备ID};SIZE:{宽}x{高}[;PORT:{端口}]
+// 设备ID由固件根据MAC地址生成，不会变化，屏幕文件保存设备ID后，路由器分配了新的IP也能找到屏幕
+const DISCOVERY_PORT: u16 = 37020;
+const DISCOVERY_REQUEST: &[u8] = b"USB-SCREEN-DISCOVER\n";
+const DISCOVERY_REPLY: &str = "ESP32-WIFI-SCREEN";
+// 等待屏幕回复的时间
+pub const DISCOVERY_TIMEOUT: Duration = Duration::from_millis(1500);
 
 // 无变化帧阈值：压缩后小于此大小认为画面没变化
 const NO_CHANGE_THRESHOLD: usize = 200;
+// 分块差分帧的分块大小(像素)
+const TILE_SIZE: u16 = 16;
+const JPEG_QUALITY: u8 = 80;
+
+// 固件支持的额外编码，老固件只支持 wflz4ke_/wflz4dl_/wflz4no_
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Codecs{
+    pub tile: bool,
+    pub jpeg: bool,
+}
+
+impl Codecs{
+    // 固件在 /display_config 的 codecs 字段中声明支持的编码，例如 ["TILE", "JPEG"]
+    pub fn negotiate(names: &[String]) -> Self{
+        let supports = |codec: &str| names.iter().any(|c| c.trim().eq_ignore_ascii_case(codec));
+        Self{ tile: supports("TILE"), jpeg: supports("JPEG") }
+    }
+}
 
-// WiFi帧差分编码器
-struct DeltaEncoder {
+// WiFi帧差分编码器, 支持差分的ESP32串口屏幕也使用同样的帧格式
+pub struct DeltaEncoder {
     prev_frame: Vec<u8>,       // 上一帧RGB565数据
     frame_count: u32,          // 帧计数
     key_frame_interval: u32,   // 关键帧间隔(默认60帧)
+    codecs: Codecs,            // 固件支持的额外编码
+    exact_reference: bool,     // 屏幕上的参考帧和 prev_frame 完全一致(JPEG关键帧之后不能发送XOR差分帧)
 }
 
 impl DeltaEncoder {
-    fn new(key_frame_interval: u32) -> Self {
+    pub fn new(key_frame_interval: u32) -> Self {
         Self {
             prev_frame: Vec::new(),
             frame_count: 0,
             key_frame_interval,
+            codecs: Codecs::default(),
+            exact_reference: false,
+        }
+    }
+
+    // 设置固件支持的编码，改变后重新发送关键帧
+    pub fn set_codecs(&mut self, codecs: Codecs) {
+        if self.codecs != codecs {
+            self.codecs = codecs;
+            self.reset();
         }
     }
 
     // 编码一帧RGB565数据
     // 返回: (编码后的数据, 帧类型描述)
-    fn encode(&mut self, rgb565_data: &[u8], width: u16, height: u16) -> (Vec<u8>, &'static str) {
+    pub fn encode(&mut self, rgb565_data: &[u8], width: u16, height: u16) -> (Vec<u8>, &'static str) {
         let need_key_frame = self.prev_frame.len() != rgb565_data.len()
             || self.frame_count == 0
             || self.frame_count % self.key_frame_interval == 0;
 
         if need_key_frame {
-            // 关键帧: 直接压缩完整数据
-            let compressed = lz4_flex::compress_prepend_size(rgb565_data);
-            
-            // 构建帧数据: MAGIC + WIDTH + HEIGHT + COMPRESSED_DATA
-            let mut frame = Vec::with_capacity(12 + compressed.len());
-            frame.extend_from_slice(WIFI_KEY_MAGIC);
-            frame.extend_from_slice(&width.to_be_bytes());
-            frame.extend_from_slice(&height.to_be_bytes());
-            frame.extend_from_slice(&compressed);
-            
-            // 保存当前帧作为参考帧
-            self.prev_frame = rgb565_data.to_vec();
+            let (frame, frame_type) = self.key_frame(rgb565_data, width, height);
+            return self.sent(rgb565_data, frame, frame_type);
+        }
+
+        // 差分帧: 计算XOR差分并压缩
+        let delta: Vec<u8> = rgb565_data.iter()
+            .zip(self.prev_frame.iter())
+            .map(|(curr, prev)| curr ^ prev)
+            .collect();
+
+        let compressed_delta = lz4_flex::compress_prepend_size(&delta);
+
+        // 如果压缩后数据很小，说明画面几乎没变化，发送无变化帧
+        if compressed_delta.len() < NO_CHANGE_THRESHOLD {
             self.frame_count = self.frame_count.wrapping_add(1);
-            
-            (frame, "KEY")
-        } else {
-            // 差分帧: 计算XOR差分并压缩
-            let delta: Vec<u8> = rgb565_data.iter()
-                .zip(self.prev_frame.iter())
-                .map(|(curr, prev)| curr ^ prev)
-                .collect();
-            
-            let compressed_delta = lz4_flex::compress_prepend_size(&delta);
-            
-            // 如果压缩后数据很小，说明画面几乎没变化，发送无变化帧
-            if compressed_delta.len() < NO_CHANGE_THRESHOLD {
-                let mut frame = Vec::with_capacity(12);
-                frame.extend_from_slice(WIFI_NOP_MAGIC);
-                frame.extend_from_slice(&width.to_be_bytes());
-                frame.extend_from_slice(&height.to_be_bytes());
-                
-                self.frame_count = self.frame_count.wrapping_add(1);
-                
-                (frame, "NOP")
-            } else {
-                let compressed_key = lz4_flex::compress_prepend_size(rgb565_data);
-                
-                // 如果差分帧比关键帧还大，使用关键帧
-                if compressed_delta.len() >= compressed_key.len() {
-                    let mut frame = Vec::with_capacity(12 + compressed_key.len());
-                    frame.extend_from_slice(WIFI_KEY_MAGIC);
-                    frame.extend_from_slice(&width.to_be_bytes());
-                    frame.extend_from_slice(&height.to_be_bytes());
-                    frame.extend_from_slice(&compressed_key);
-                    
-                    self.prev_frame = rgb565_data.to_vec();
-                    self.frame_count = self.frame_count.wrapping_add(1);
-                    
-                    (frame, "KEY")
-                } else {
-                    // 使用差分帧
-                    let mut frame = Vec::with_capacity(12 + compressed_delta.len());
-                    frame.extend_from_slice(WIFI_DLT_MAGIC);
-                    frame.extend_from_slice(&width.to_be_bytes());
-                    frame.extend_from_slice(&height.to_be_bytes());
-                    frame.extend_from_slice(&compressed_delta);
-                    
-                    // 更新参考帧
-                    self.prev_frame = rgb565_data.to_vec();
-                    self.frame_count = self.frame_count.wrapping_add(1);
-                    
-                    (frame, "DLT")
+            return (frame(WIFI_NOP_MAGIC, width, height, &[]), "NOP");
+        }
+
+        // 选择数据量最小的编码，一样大时使用关键帧
+        let mut candidates = vec![self.key_frame(rgb565_data, width, height)];
+        if self.exact_reference {
+            candidates.push((frame(WIFI_DLT_MAGIC, width, height, &compressed_delta), "DLT"));
+        }
+        if self.codecs.tile {
+            if let Some(tiles) = self.tile_frame(rgb565_data, width, height) {
+                candidates.push((tiles, "TILE"));
+            }
+        }
+        let (frame, frame_type) = candidates.into_iter().min_by_key(|(frame, _)| frame.len()).unwrap();
+        self.sent(rgb565_data, frame, frame_type)
+    }
+
+    // 关键帧: 压缩完整数据，压缩率很低(照片、摄像头画面)并且固件支持时改用JPEG
+    fn key_frame(&self, rgb565_data: &[u8], width: u16, height: u16) -> (Vec<u8>, &'static str) {
+        let compressed = lz4_flex::compress_prepend_size(rgb565_data);
+        if self.codecs.jpeg && compressed.len() * 2 > rgb565_data.len() {
+            match encode_jpeg(rgb565_data, width, height) {
+                Ok(jpeg) if jpeg.len() < compressed.len() => return (frame(WIFI_JPEG_MAGIC, width, height, &jpeg), "JPEG"),
+                Ok(_) => (),
+                Err(err) => warn!("JPEG编码失败:{err:?}"),
+            }
+        }
+        (frame(WIFI_KEY_MAGIC, width, height, &compressed), "KEY")
+    }
+
+    // 分块差分帧: 只发送和上一帧相比发生变化的分块
+    fn tile_frame(&self, rgb565_data: &[u8], width: u16, height: u16) -> Option<Vec<u8>> {
+        let (w, h, tile) = (width as usize, height as usize, TILE_SIZE as usize);
+        if rgb565_data.len() != w * h * 2 || self.prev_frame.len() != rgb565_data.len() {
+            return None;
+        }
+        let mut positions = vec![];
+        let mut pixels = vec![];
+        for y in (0..h).step_by(tile) {
+            for x in (0..w).step_by(tile) {
+                let tile_width = tile.min(w - x);
+                let rows = (y..h.min(y + tile)).map(|row| (row * w + x) * 2..(row * w + x + tile_width) * 2);
+                if rows.clone().any(|r| rgb565_data[r.clone()] != self.prev_frame[r]) {
+                    positions.extend_from_slice(&(x as u16).to_be_bytes());
+                    positions.extend_from_slice(&(y as u16).to_be_bytes());
+                    rows.for_each(|r| pixels.extend_from_slice(&rgb565_data[r]));
                 }
             }
         }
+        let count = u16::try_from(positions.len() / 4).ok()?;
+        let mut data = Vec::with_capacity(4 + positions.len() + pixels.len());
+        data.extend_from_slice(&TILE_SIZE.to_be_bytes());
+        data.extend_from_slice(&count.to_be_bytes());
+        data.extend_from_slice(&positions);
+        data.extend_from_slice(&pixels);
+        Some(frame(WIFI_TILE_MAGIC, width, height, &lz4_flex::compress_prepend_size(&data)))
+    }
+
+    // 更新参考帧
+    fn sent(&mut self, rgb565_data: &[u8], frame: Vec<u8>, frame_type: &'static str) -> (Vec<u8>, &'static str) {
+        match frame_type {
+            "KEY" => self.exact_reference = true,
+            "JPEG" => self.exact_reference = false,
+            _ => (),
+        }
+        self.prev_frame = rgb565_data.to_vec();
+        self.frame_count = self.frame_count.wrapping_add(1);
+        (frame, frame_type)
     }
 
     // 重置编码器状态
-    fn reset(&mut self) {
+    pub fn reset(&mut self) {
         self.prev_frame.clear();
         self.frame_count = 0;
     }
 }
 
+// 构建帧数据: MAGIC + WIDTH + HEIGHT + DATA
+fn frame(magic: &[u8; 8], width: u16, height: u16, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(12 + data.len());
+    frame.extend_from_slice(magic);
+    frame.extend_from_slice(&width.to_be_bytes());
+    frame.extend_from_slice(&height.to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+fn encode_jpeg(rgb565_data: &[u8], width: u16, height: u16) -> Result<Vec<u8>> {
+    let rgb888 = rgb565_be_to_rgb888(rgb565_data, width as usize, height as usize);
+    let mut jpeg = vec![];
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(&rgb888, width as u32, height as u32, ExtendedColorType::Rgb8)?;
+    Ok(jpeg)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct DisplayConfig{
     display_type: Option<String>,
     rotated_width: u32,
-    rotated_height: u32
+    rotated_height: u32,
+    // 固件支持的额外编码，老固件没有这个字段
+    #[serde(default)]
+    codecs: Vec<String>,
+}
+
+// WiFi屏幕的分辨率和画面的宽高比不一致时的缩放方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum ScaleMode{
+    // 拉伸到屏幕大小，画面会变形
+    #[default]
+    Stretch,
+    // 保持宽高比完整显示，空白部分填充背景色
+    Fit,
+    // 保持宽高比填满屏幕，裁剪超出的部分
+    Fill,
+    // 按整数倍缩放(最近邻)，像素清晰，空白部分填充背景色
+    Integer,
+}
+
+impl ScaleMode{
+    pub fn parse(s: &str) -> Result<Self, String>{
+        match s.trim().to_lowercase().as_str(){
+            "stretch" => Ok(Self::Stretch),
+            "fit" => Ok(Self::Fit),
+            "fill" => Ok(Self::Fill),
+            "integer" => Ok(Self::Integer),
+            _ => Err(format!("缩放方式为 stretch、fit、fill、integer: {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Scaling{
+    #[serde(default)]
+    pub mode: ScaleMode,
+    // Fit、Integer 空白部分的颜色(RGB)
+    #[serde(default)]
+    pub background: [u8; 3],
+}
+
+impl Scaling{
+    // 解析 RRGGBB 或 #RRGGBB 格式的背景色
+    pub fn parse_color(s: &str) -> Result<[u8; 3], String>{
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()){
+            return Err(format!("颜色格式为 RRGGBB: {s}"));
+        }
+        let v = u32::from_str_radix(hex, 16).map_err(|err| err.to_string())?;
+        Ok([(v >> 16) as u8, (v >> 8) as u8, v as u8])
+    }
+}
+
+// WiFi屏幕的编码偏好
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum WifiCodec{
+    // 使用固件声明支持的所有编码，照片类画面使用有损的JPEG关键帧
+    #[default]
+    Auto,
+    // 只使用无损编码，不发送JPEG关键帧
+    Lossless,
+}
+
+impl WifiCodec{
+    pub fn parse(s: &str) -> Result<Self, String>{
+        match s.trim().to_lowercase().as_str(){
+            "auto" => Ok(Self::Auto),
+            "lossless" => Ok(Self::Lossless),
+            _ => Err(format!("编码为 auto、lossless: {s}")),
+        }
+    }
+
+    // 从固件支持的编码中去掉不允许使用的编码
+    fn apply(self, codecs: Codecs) -> Codecs{
+        match self{
+            Self::Auto => codecs,
+            Self::Lossless => Codecs{ jpeg: false, ..codecs },
+        }
+    }
 }
 
 pub enum Message{
     Connect(String),
+    // 按设备ID查找屏幕当前的地址后连接，找不到时使用上次找到的地址或者保存的地址
+    ConnectDevice(String, Option<String>),
     Disconnect,
-    Image(RgbaImage)
+    Image(RgbaImage),
+    // 画面缩放方式
+    Scaling(Scaling),
+    // 编码偏好
+    Codec(WifiCodec),
+    // 加密和认证设置，下次连接时使用
+    Security(WifiSecurity),
+    // 背光亮度(0~100)
+    Brightness(u8),
+    // 打开或关闭屏幕
+    DisplayOn(bool),
 }
 
 #[derive(Debug, Clone)]
@@ -136,6 +305,8 @@ pub struct StatusInfo{
     pub ip: Option<String>,
     pub status: Status,
     pub delay_ms: u64,
+    // 最近一次连接失败的原因
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -145,6 +316,8 @@ pub enum Status{
     ConnectFail,
     Disconnected,
     Connecting,
+    // 令牌或证书被拒绝，不会自动重试
+    AuthFailed,
 }
 
 impl Status{
@@ -155,68 +328,78 @@ impl Status{
             Status::ConnectFail => "连接失败",
             Status::Disconnected => "连接断开",
             Status::Connecting => "正在连接",
+            Status::AuthFailed => "认证失败",
         }
     }
 }
 
-static CONFIG: Lazy<Mutex<(StatusInfo, Sender<Message>)>> = Lazy::new(|| {
-    let (sender, recv) = bounded(1);
-    let _ = std::thread::spawn(move ||{
-        start(recv);
-    });
-    Mutex::new((StatusInfo{
-        ip: None,
-        status: Status::NotConnected,
-        delay_ms: 1,
-    }, sender))
-});
-
-fn set_status(ip: Option<String>, status: Status) -> Result<()>{
-    let mut config = CONFIG.lock().map_err(|err| anyhow!("{err:?}"))?;
-    config.0.status = status;
-    config.0.ip = ip;
-    Ok(())
+// 一个WiFi屏幕连接: 每个连接有自己的上传线程、状态、差分编码器和屏幕参数，同一个进程可以同时驱动多个WiFi屏幕
+// 所有句柄释放后上传线程关闭连接并退出
+#[derive(Clone)]
+pub struct WifiScreen{
+    status: Arc<Mutex<StatusInfo>>,
+    sender: Sender<Message>,
 }
 
-pub fn set_delay_ms(delay_ms: u64) -> Result<()>{
-    let mut config = CONFIG.lock().map_err(|err| anyhow!("{err:?}"))?;
-    config.0.delay_ms = delay_ms;
-    Ok(())
-}
+impl WifiScreen{
+    pub fn new() -> Result<Self>{
+        let (sender, recv) = bounded(1);
+        let status = Arc::new(Mutex::new(StatusInfo{
+            ip: None,
+            status: Status::NotConnected,
+            delay_ms: 1,
+            error: None,
+        }));
+        let thread_status = status.clone();
+        std::thread::Builder::new()
+            .name("wifi-screen".to_string())
+            .spawn(move ||{
+                start(recv, &thread_status);
+            })?;
+        Ok(Self{ status, sender })
+    }
 
-pub fn send_message(msg: Message) -> Result<()>{
-    let sender = {
-        let config = CONFIG.lock().map_err(|err| anyhow!("{err:?}"))?;
-        let s = config.1.clone();
-        drop(config);
-        s
-    };
-    sender.send(msg)?;
-    Ok(())
+    pub fn send_message(&self, msg: Message) -> Result<()>{
+        self.sender.send(msg)?;
+        Ok(())
+    }
+
+    pub fn try_send_message(&self, msg: Message) -> Result<()>{
+        self.sender.try_send(msg)?;
+        Ok(())
+    }
+
+    pub fn get_status(&self) -> Result<StatusInfo>{
+        let status = self.status.lock().map_err(|err| anyhow!("{err:?}"))?;
+        Ok(status.clone())
+    }
 }
 
-pub fn try_send_message(msg: Message) -> Result<()>{
-    let config = CONFIG.lock().map_err(|err| anyhow!("{err:?}"))?;
-    config.1.try_send(msg)?;
+fn set_status(status_info: &Mutex<StatusInfo>, ip: Option<String>, status: Status) -> Result<()>{
+    let mut status_info = status_info.lock().map_err(|err| anyhow!("{err:?}"))?;
+    status_info.status = status;
+    status_info.ip = ip;
     Ok(())
 }
 
-pub fn get_status() -> Result<StatusInfo>{
-    let config = CONFIG.lock().map_err(|err| anyhow!("{err:?}"))?;
-    Ok(config.0.clone())
+// 连接失败，认证错误设置为 AuthFailed
+fn set_failed(status_info: &Mutex<StatusInfo>, err: &anyhow::Error) -> Result<()>{
+    let mut status_info = status_info.lock().map_err(|err| anyhow!("{err:?}"))?;
+    status_info.status = if err.is::<AuthError>(){ Status::AuthFailed }else{ Status::ConnectFail };
+    status_info.error = Some(format!("{err}"));
+    Ok(())
 }
 
-fn get_display_config(ip: &str) -> Result<DisplayConfig>{
-    let resp = reqwest::blocking::Client::builder()
-    .timeout(Duration::from_secs(2))
-    .build()?
-    .get(&format!("http://{ip}/display_config"))
-    .send()?
+fn get_display_config(ip: &str, security: &WifiSecurity) -> Result<DisplayConfig>{
+    let resp = security.http_client(Some(Duration::from_secs(2)))?
+    .get(security.http_url(ip, "/display_config"))
+    .send();
+    let resp = WifiSecurity::check_response(resp)?
     .json::<DisplayConfig>()?;
     Ok(resp)
 }
 
-fn start(receiver: Receiver<Message>){
+fn start(receiver: Receiver<Message>, status: &Mutex<StatusInfo>){
     let mut socket: Option<WebSocket<MaybeTlsStream<TcpStream>>> = None;
     let mut screen_ip = String::new();
     let mut delta_encoder = DeltaEncoder::new(60);
@@ -225,60 +408,141 @@ fn start(receiver: Receiver<Message>){
 
     let mut display_config = None;
     let mut connected = false;
+    // 最近一次设置的亮度和屏幕开关，重新连接后再次发送
+    let mut brightness: Option<u8> = None;
+    let mut display_on = true;
+    let mut scaling = Scaling::default();
+    let mut codec = WifiCodec::default();
+    let mut security = WifiSecurity::default();
+    // 断开后重新连接时不经过队列，直接在本线程处理
+    let mut pending: Option<Message> = None;
+    // 局域网发现找到的地址: 设备ID -> IP
+    let mut device_ips: HashMap<String, String> = HashMap::new();
     
     loop{
-        match receiver.recv(){
+        let msg = match pending.take(){
+            Some(msg) => Ok(msg),
+            None => receiver.recv(),
+        };
+        match msg{
             Ok(msg) => {
                 match msg{
                     Message::Disconnect => {
                         screen_ip = String::new();
                         delta_encoder.reset();
-                        if let Ok(mut cfg) = CONFIG.lock(){
-                            cfg.0.status = Status::Disconnected
+                        if let Ok(mut status) = status.lock(){
+                            status.status = Status::Disconnected
                         }
                         if let Some(mut s) = socket.take(){
                             let _ = s.close(None);
                         }
                     }
+                    Message::ConnectDevice(id, saved_ip) => {
+                        // 局域网发现需要等待回复，在上传线程中执行，不阻塞渲染
+                        let _ = set_status(status, None, Status::Connecting);
+                        match resolve_device_id(&id){
+                            Some(ip) => {
+                                if device_ips.get(&id) != Some(&ip){
+                                    info!("WiFi屏幕 {id} 的地址改变: {:?} -> {ip}", device_ips.get(&id));
+                                }
+                                device_ips.insert(id.clone(), ip);
+                            }
+                            None => info!("未找到设备ID为 {id} 的WiFi屏幕"),
+                        }
+                        match device_ips.get(&id).cloned().or(saved_ip){
+                            Some(ip) => pending = Some(Message::Connect(ip)),
+                            None => {
+                                let _ = set_failed(status, &anyhow!("未找到设备ID为 {id} 的WiFi屏幕"));
+                            }
+                        }
+                    }
                     Message::Connect(ip) => {
                         screen_ip = ip.clone();
                         delta_encoder.reset();
-                        if let Ok(cfg) = get_display_config(&ip){
-                            display_config = Some(cfg);
-                        }else{
-                            eprintln!("display config获取失败!");
+                        match get_display_config(&ip, &security){
+                            Ok(cfg) => {
+                                let codecs = Codecs::negotiate(&cfg.codecs);
+                                println!("屏幕支持的编码:{codecs:?} 编码偏好:{codec:?}");
+                                delta_encoder.set_codecs(codec.apply(codecs));
+                                display_config = Some(cfg);
+                            }
+                            Err(err) if err.is::<AuthError>() => {
+                                eprintln!("WiFi屏幕认证失败 {ip}: {err}");
+                                if let Some(mut s) = socket.take(){
+                                    let _ = s.close(None);
+                                }
+                                connected = false;
+                                let _ = set_failed(status, &err);
+                                continue;
+                            }
+                            Err(_) => eprintln!("display config获取失败!"),
                         }
                         println!("接收到 serverIP...");
-                        connected = connect_socket(ip, &mut socket).is_ok();
+                        connected = connect_socket(ip, &mut socket, status, &security).is_ok();
+                        if let Some(s) = socket.as_mut(){
+                            if let Some(brightness) = brightness{
+                                let _ = send_control(s, &format!("BRIGHTNESS:{brightness}"));
+                            }
+                            if !display_on{
+                                let _ = send_control(s, "DISPLAY:OFF");
+                            }
+                        }
+                    }
+                    Message::Brightness(value) => {
+                        let value = value.min(100);
+                        brightness = Some(value);
+                        if let Some(s) = socket.as_mut(){
+                            if let Err(err) = send_control(s, &format!("BRIGHTNESS:{value}")){
+                                eprintln!("设置亮度失败:{err:?}");
+                            }
+                        }
+                    }
+                    Message::Scaling(value) => {
+                        scaling = value;
+                    }
+                    Message::Codec(value) => {
+                        codec = value;
+                        if let Some(cfg) = display_config.as_ref(){
+                            delta_encoder.set_codecs(codec.apply(Codecs::negotiate(&cfg.codecs)));
+                        }
                     }
-                    Message::Image(mut image) => {
+                    Message::Security(value) => {
+                        security = value;
+                    }
+                    Message::DisplayOn(on) => {
+                        display_on = on;
+                        if let Some(s) = socket.as_mut(){
+                            if let Err(err) = send_control(s, if on { "DISPLAY:ON" } else { "DISPLAY:OFF" }){
+                                eprintln!("设置屏幕开关失败:{err:?}");
+                            }
+                        }
+                        // 屏幕关闭期间的画面可能没有显示，打开后发送关键帧
+                        delta_encoder.reset();
+                    }
+                    Message::Image(image) => {
                         let delay_ms = {
-                            if let Ok(mut cfg) = CONFIG.try_lock(){
-                                cfg.0.status = if connected{
+                            if let Ok(mut status) = status.try_lock(){
+                                status.status = if connected{
                                     Status::Connected
                                 }else{
                                     Status::Disconnected
                                 };
-                                let v = cfg.0.delay_ms;
-                                drop(cfg);
-                                v
+                                status.delay_ms
                             }else{
                                 1
                             }
                         };
                         if display_config.is_none(){
-                            match get_display_config(&screen_ip){
+                            match get_display_config(&screen_ip, &security){
                                 Ok(cfg) => {
+                                    delta_encoder.set_codecs(codec.apply(Codecs::negotiate(&cfg.codecs)));
                                     display_config = Some(cfg);
                                 }
                                 Err(_err) => {
                                     eprintln!("Message::Image display config获取失败!");
                                     std::thread::sleep(Duration::from_secs(3));
-                                    let screen_ip_clone = screen_ip.clone();
-                                    std::thread::spawn(move ||{
-                                        let r = send_message(Message::Connect(screen_ip_clone));
-                                        println!("重新连接 SetIp {r:?}...");
-                                    });
+                                    println!("重新连接 {screen_ip}...");
+                                    pending = Some(Message::Connect(screen_ip.clone()));
                                 }
                             }
                         }
@@ -297,7 +561,7 @@ fn start(receiver: Receiver<Message>){
                                 let t1 = Instant::now();
                                 
                                 // 缩放图像
-                                let img = match fast_resize(&mut image, dst_width, dst_height){
+                                let img = match scale_image(&image, dst_width, dst_height, scaling){
                                     Ok(v) => v,
                                     Err(err) => {
                                         eprintln!("图片压缩失败:{}", err.root_cause());
@@ -311,11 +575,7 @@ fn start(receiver: Receiver<Message>){
                                 // 使用差分编码
                                 let (out, frame_type) = delta_encoder.encode(&rgb565, dst_width as u16, dst_height as u16);
                                 let encode_ms = t1.elapsed().as_millis();
-                                
-                                // 设置读取超时3秒
-                                if let tungstenite::stream::MaybeTlsStream::Plain(stream) = s.get_mut() {
-                                    let _ = stream.set_read_timeout(Some(Duration::from_secs(3)));
-                                }
+                                // 连接时已经设置了3秒的读取超时
                                 
                                 // 发送帧
                                 let send_start = Instant::now();
@@ -371,73 +631,315 @@ fn start(receiver: Receiver<Message>){
                                 let _ = s.close(None);
                             }
                             delta_encoder.reset();
-                            let _ = set_status(None, Status::Disconnected);
+                            let _ = set_status(status, None, Status::Disconnected);
                             println!("连接断开 3秒后重连:{screen_ip}");
                             if screen_ip.len() > 0{
                                 std::thread::sleep(Duration::from_secs(3));
-                                let screen_ip_clone = screen_ip.clone();
-                                std::thread::spawn(move ||{
-                                    let r = send_message(Message::Connect(screen_ip_clone));
-                                    println!("重新连接 SetIp {r:?}...");
-                                });
+                                pending = Some(Message::Connect(screen_ip.clone()));
                             }
                         }
                     }
                 }
             }
             Err(_err) => {
-                std::thread::sleep(Duration::from_millis(10));
+                // 所有句柄都已经释放
+                if let Some(mut s) = socket.take(){
+                    let _ = s.close(None);
+                }
+                println!("upload线程退出:{screen_ip}");
+                return;
             }
         }
     }
 }
 
-fn connect_socket(ip: String, old_socket: &mut Option<WebSocket<MaybeTlsStream<TcpStream>>>) -> Result<()>{
+// 控制命令使用文本消息发送(图像帧是二进制消息): BRIGHTNESS:{0~100}、DISPLAY:ON、DISPLAY:OFF
+// 固件不等待ACK，不认识的命令直接忽略
+fn send_control(socket: &mut WebSocket<MaybeTlsStream<TcpStream>>, command: &str) -> Result<()>{
+    socket.write(tungstenite::Message::Text(command.into()))?;
+    socket.flush()?;
+    Ok(())
+}
+
+fn connect_socket(ip: String, old_socket: &mut Option<WebSocket<MaybeTlsStream<TcpStream>>>, status: &Mutex<StatusInfo>, security: &WifiSecurity) -> Result<()>{
     if let Some(mut s) = old_socket.take(){
         let _ = s.close(None);
     }
-    let _ = set_status(Some(ip.clone()), Status::Connecting);
-    let url = format!("ws://{ip}/ws");
-    println!("开始连接:{url}");
-    if let Ok((s, _resp)) = connect(url){
-        *old_socket = Some(s);
-        let ret = set_status(None, Status::Connected);
-        println!("连接成功{ip}.. 设置状态:{ret:?}");
-    }else{
-        println!("连接失败{ip}..");
-        let _ = set_status(None, Status::ConnectFail);
+    let _ = set_status(status, Some(ip.clone()), Status::Connecting);
+    println!("开始连接:{}", security.ws_url(&ip));
+    match security.connect_websocket(&ip){
+        Ok(s) => {
+            *old_socket = Some(s);
+            let ret = set_status(status, None, Status::Connected);
+            if let Ok(mut status) = status.lock(){
+                status.error = None;
+            }
+            println!("连接成功{ip}.. 设置状态:{ret:?}");
+        }
+        Err(err) => {
+            println!("连接失败{ip}.. {err}");
+            let _ = set_failed(status, &err);
+        }
     }
     Ok(())
 }
 
-fn fast_resize(src: &mut RgbaImage, dst_width: u32, dst_height: u32) -> Result<RgbImage>{
-    let mut dst_image = Image::new(
-        dst_width,
-        dst_height,
-        fast_image_resize::PixelType::U8x3,
-    );
-    let mut src:RgbImage = src.convert();
-    if src.width() != dst_width || src.height() != dst_height{
-        let v = Image::from_slice_u8(src.width(), src.height(), src.as_mut(), fast_image_resize::PixelType::U8x3)?;
-        let mut resizer = Resizer::new();
-        resizer.resize(&v, &mut dst_image, None)?;
-        Ok(RgbImage::from_raw(dst_image.width(), dst_image.height(), dst_image.buffer().to_vec()).unwrap())
-    }else{
-        Ok(src.convert())
+// 按照缩放方式把画面缩放到屏幕的分辨率
+fn scale_image(src: &RgbaImage, dst_width: u32, dst_height: u32, scaling: Scaling) -> Result<RgbImage>{
+    let src: RgbImage = src.convert();
+    let (src_width, src_height) = src.dimensions();
+    if (src_width, src_height) == (dst_width, dst_height) || src_width == 0 || src_height == 0{
+        return Ok(src);
+    }
+    let scale_x = dst_width as f64 / src_width as f64;
+    let scale_y = dst_height as f64 / src_height as f64;
+    match scaling.mode{
+        ScaleMode::Stretch => resize(&src, dst_width, dst_height, &ResizeOptions::new()),
+        ScaleMode::Fill => {
+            // 按照屏幕的宽高比裁剪画面中间的部分，再缩放到屏幕大小
+            let scale = scale_x.max(scale_y);
+            let (crop_width, crop_height) = (dst_width as f64 / scale, dst_height as f64 / scale);
+            let options = ResizeOptions::new().crop(
+                (src_width as f64 - crop_width) / 2.,
+                (src_height as f64 - crop_height) / 2.,
+                crop_width,
+                crop_height,
+            );
+            resize(&src, dst_width, dst_height, &options)
+        }
+        ScaleMode::Fit => {
+            let scale = scale_x.min(scale_y);
+            let width = ((src_width as f64 * scale).round() as u32).clamp(1, dst_width);
+            let height = ((src_height as f64 * scale).round() as u32).clamp(1, dst_height);
+            let img = resize(&src, width, height, &ResizeOptions::new())?;
+            Ok(letterbox(&img, dst_width, dst_height, scaling.background))
+        }
+        ScaleMode::Integer => {
+            // 放大时取能放下的最大整数倍，缩小时取整数分之一
+            let (width, height) = if src_width <= dst_width && src_height <= dst_height{
+                let n = (dst_width / src_width).min(dst_height / src_height);
+                (src_width * n, src_height * n)
+            }else{
+                let n = src_width.div_ceil(dst_width).max(src_height.div_ceil(dst_height));
+                ((src_width / n).max(1), (src_height / n).max(1))
+            };
+            let img = resize(&src, width, height, &ResizeOptions::new().resize_alg(ResizeAlg::Nearest))?;
+            Ok(letterbox(&img, dst_width, dst_height, scaling.background))
+        }
     }
 }
 
-// 获取wifi屏幕参数，测试是否可以连接成功
-pub fn test_screen_sync(ip: String) -> Result<()>{
-    let resp = reqwest::blocking::get(&format!("http://{ip}/display_config"))?
+fn resize(src: &RgbImage, dst_width: u32, dst_height: u32, options: &ResizeOptions) -> Result<RgbImage>{
+    let src = ImageRef::new(src.width(), src.height(), src.as_raw(), PixelType::U8x3)?;
+    let mut dst = Image::new(dst_width, dst_height, PixelType::U8x3);
+    Resizer::new().resize(&src, &mut dst, Some(options))?;
+    RgbImage::from_raw(dst_width, dst_height, dst.into_vec()).ok_or(anyhow!("图像大小错误"))
+}
+
+// 把画面放在屏幕中间，空白部分填充背景色
+fn letterbox(img: &RgbImage, width: u32, height: u32, background: [u8; 3]) -> RgbImage{
+    let mut canvas = RgbImage::from_pixel(width, height, Rgb(background));
+    let x = width.saturating_sub(img.width()) / 2;
+    let y = height.saturating_sub(img.height()) / 2;
+    image::imageops::replace(&mut canvas, img, x as i64, y as i64);
+    canvas
+}
+
+// 获取wifi屏幕参数，测试是否可以连接成功(编辑器中输入IP后调用)
+#[cfg(feature = "editor")]
+pub fn test_screen_sync(ip: String, security: &WifiSecurity) -> Result<()>{
+    let client = security.http_client(None)?;
+    let resp = WifiSecurity::check_response(client.get(security.http_url(&ip, "/display_config")).send())?
         .json::<DisplayConfig>()?;
     println!("屏幕大小:{}x{}", resp.rotated_width, resp.rotated_height);
     // 显示hello
     let json = r#"[{"Rectangle":{"fill_color":"black","height":240,"width":240,"stroke_width":0,"left":0,"top":0}},{"Text":{"color":"white","size":20,"text":"Hello!","x":10,"y":15}},{"Text":{"color":"white","size":20,"text":"USB Screen","x":10,"y":40}}]"#;
-    let _resp = reqwest::blocking::Client::new()
-        .post(&format!("http://{ip}/draw_canvas"))
+    let _resp = client
+        .post(security.http_url(&ip, "/draw_canvas"))
         .body(json.as_bytes())
         .send()?
         .text()?;
     Ok(())
 }
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredScreen{
+    pub id: String,
+    // 屏幕地址，格式和 device_ip 一致(IP 或 IP:端口)
+    pub ip: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn parse_discovery_reply(reply: &str, addr: IpAddr) -> Option<DiscoveredScreen>{
+    let mut fields = reply.trim().split(';');
+    if fields.next()? != DISCOVERY_REPLY{
+        return None;
+    }
+    let (mut id, mut size, mut port) = (None, (0, 0), None);
+    for field in fields{
+        match field.split_once(':'){
+            Some(("ID", v)) if !v.is_empty() => id = Some(v.to_string()),
+            Some(("SIZE", v)) => {
+                if let Some((w, h)) = v.split_once('x'){
+                    size = (w.parse().unwrap_or(0), h.parse().unwrap_or(0));
+                }
+            }
+            Some(("PORT", v)) => port = v.parse::<u16>().ok(),
+            _ => {}
+        }
+    }
+    let ip = match port{
+        Some(port) if port != 80 => format!("{addr}:{port}"),
+        _ => addr.to_string(),
+    };
+    Some(DiscoveredScreen{ id: id?, ip, width: size.0, height: size.1 })
+}
+
+// 广播查找局域网中的WiFi屏幕，等待 timeout 后返回所有回复的屏幕
+pub fn discover(timeout: Duration) -> Result<Vec<DiscoveredScreen>>{
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_broadcast(true)?;
+    socket.send_to(DISCOVERY_REQUEST, ("255.255.255.255", DISCOVERY_PORT))?;
+    let deadline = Instant::now() + timeout;
+    let mut screens: Vec<DiscoveredScreen> = vec![];
+    let mut buf = [0u8; 512];
+    loop{
+        let remain = deadline.saturating_duration_since(Instant::now());
+        if remain.is_zero(){
+            break;
+        }
+        socket.set_read_timeout(Some(remain))?;
+        match socket.recv_from(&mut buf){
+            Ok((n, addr)) => {
+                let reply = String::from_utf8_lossy(&buf[..n]);
+                match parse_discovery_reply(&reply, addr.ip()){
+                    Some(screen) => {
+                        if !screens.iter().any(|s| s.id == screen.id){
+                            info!("发现WiFi屏幕:{screen:?}");
+                            screens.push(screen);
+                        }
+                    }
+                    None => info!("忽略无效的发现回复 {addr}: {reply}"),
+                }
+            }
+            Err(err) if matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(screens)
+}
+
+// 根据设备ID查找屏幕当前的地址
+pub fn resolve_device_id(id: &str) -> Option<String>{
+    match discover(DISCOVERY_TIMEOUT){
+        Ok(screens) => screens.into_iter().find(|s| s.id == id).map(|s| s.ip),
+        Err(err) => {
+            info!("查找WiFi屏幕失败:{err:?}");
+            None
+        }
+    }
+}
+
+#[test]
+fn test_parse_discovery_reply(){
+    let addr: IpAddr = "192.168.1.20".parse().unwrap();
+    assert_eq!(
+        parse_discovery_reply("ESP32-WIFI-SCREEN;ID:A4CF12F00D01;SIZE:320x240\n", addr),
+        Some(DiscoveredScreen{ id: "A4CF12F00D01".to_string(), ip: "192.168.1.20".to_string(), width: 320, height: 240 })
+    );
+    assert_eq!(
+        parse_discovery_reply("ESP32-WIFI-SCREEN;ID:A4CF12F00D01;SIZE:320x240;PORT:8080", addr).map(|s| s.ip),
+        Some("192.168.1.20:8080".to_string())
+    );
+    // 没有设备ID或者不是屏幕的回复
+    assert_eq!(parse_discovery_reply("ESP32-WIFI-SCREEN;SIZE:320x240", addr), None);
+    assert_eq!(parse_discovery_reply("USB-SCREEN-DISCOVER", addr), None);
+}
+
+#[test]
+fn test_scale_image(){
+    // 320x240 的白色画面缩放到 240x240 的屏幕
+    let src = RgbaImage::from_pixel(320, 240, image::Rgba([255, 255, 255, 255]));
+    let scaling = |mode| Scaling{ mode, background: [0, 0, 255] };
+
+    let img = scale_image(&src, 240, 240, scaling(ScaleMode::Stretch)).unwrap();
+    assert_eq!(img.dimensions(), (240, 240));
+    assert_eq!(img.get_pixel(120, 0), &Rgb([255, 255, 255]));
+
+    // 上下留出背景色
+    let img = scale_image(&src, 240, 240, scaling(ScaleMode::Fit)).unwrap();
+    assert_eq!(img.dimensions(), (240, 240));
+    assert_eq!(img.get_pixel(120, 0), &Rgb([0, 0, 255]));
+    assert_eq!(img.get_pixel(120, 120), &Rgb([255, 255, 255]));
+    assert_eq!(img.get_pixel(120, 239), &Rgb([0, 0, 255]));
+
+    // 裁剪左右两边，没有背景色
+    let img = scale_image(&src, 240, 240, scaling(ScaleMode::Fill)).unwrap();
+    assert_eq!(img.dimensions(), (240, 240));
+    assert_eq!(img.get_pixel(0, 0), &Rgb([255, 255, 255]));
+
+    // 缩小为1/2，居中显示 160x120
+    let img = scale_image(&src, 240, 240, scaling(ScaleMode::Integer)).unwrap();
+    assert_eq!(img.get_pixel(39, 60), &Rgb([0, 0, 255]));
+    assert_eq!(img.get_pixel(40, 60), &Rgb([255, 255, 255]));
+    assert_eq!(img.get_pixel(199, 179), &Rgb([255, 255, 255]));
+    assert_eq!(img.get_pixel(200, 179), &Rgb([0, 0, 255]));
+
+    // 160x128 放大2倍到 480x320
+    let src = RgbaImage::from_pixel(160, 128, image::Rgba([255, 255, 255, 255]));
+    let img = scale_image(&src, 480, 320, scaling(ScaleMode::Integer)).unwrap();
+    assert_eq!(img.get_pixel(79, 160), &Rgb([0, 0, 255]));
+    assert_eq!(img.get_pixel(80, 160), &Rgb([255, 255, 255]));
+
+    assert_eq!(Scaling::parse_color("#10A0ff"), Ok([0x10, 0xa0, 0xff]));
+    assert!(Scaling::parse_color("+12345").is_err());
+    assert_eq!(ScaleMode::parse("Fit"), Ok(ScaleMode::Fit));
+}
+
+#[test]
+fn test_delta_encoder_codecs(){
+    let (width, height) = (64u16, 48u16);
+    // 伪随机噪声(照片一类的画面，LZ4几乎无法压缩)
+    let mut seed = 1u32;
+    let noise: Vec<u8> = (0..width as usize * height as usize * 2).map(|_| {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        (seed >> 16) as u8
+    }).collect();
+    let mut changed = noise.clone();
+    // 第(1,1)个分块变为白色
+    for y in 16..32 {
+        let offset = (y * width as usize + 16) * 2;
+        changed[offset..offset + 32].fill(0xFF);
+    }
+
+    // 老固件只使用 wflz4ke_/wflz4dl_
+    let mut encoder = DeltaEncoder::new(60);
+    assert_eq!(encoder.encode(&noise, width, height).1, "KEY");
+    assert_eq!(encoder.encode(&changed, width, height).1, "DLT");
+
+    // 分块差分帧只包含变化的分块
+    encoder.set_codecs(Codecs::negotiate(&["tile".to_string()]));
+    assert_eq!(encoder.encode(&noise, width, height).1, "KEY");
+    let (frame, frame_type) = encoder.encode(&changed, width, height);
+    assert_eq!(frame_type, "TILE");
+    assert_eq!(&frame[0..8], WIFI_TILE_MAGIC);
+    let data = lz4_flex::decompress_size_prepended(&frame[12..]).unwrap();
+    assert_eq!(&data[0..8], &[0, 16, 0, 1, 0, 16, 0, 16]);
+    assert_eq!(data.len(), 8 + 16 * 16 * 2);
+    assert!(data[8..].iter().all(|v| *v == 0xFF));
+
+    // 照片画面使用JPEG关键帧，之后不能再发送XOR差分帧
+    encoder.set_codecs(Codecs::negotiate(&["JPEG".to_string()]));
+    let (frame, frame_type) = encoder.encode(&noise, width, height);
+    assert_eq!(frame_type, "JPEG");
+    assert!(image::load_from_memory(&frame[12..]).is_ok());
+    assert_ne!(encoder.encode(&changed, width, height).1, "DLT");
+
+    // 只使用无损编码时不发送JPEG关键帧
+    encoder.set_codecs(WifiCodec::Lossless.apply(Codecs::negotiate(&["JPEG".to_string(), "TILE".to_string()])));
+    assert_eq!(encoder.encode(&noise, width, height).1, "KEY");
+    assert_eq!(encoder.encode(&changed, width, height).1, "TILE");
+    assert_eq!(WifiCodec::parse("Lossless"), Ok(WifiCodec::Lossless));
+    assert!(WifiCodec::parse("jpeg").is_err());
+}
@@ -0,0 +1,228 @@
+use std::{fmt, net::TcpStream, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use aws_lc_rs::digest::{digest, SHA256};
+use reqwest::{blocking::{Client, Response}, header::{HeaderMap, HeaderValue, AUTHORIZATION}, StatusCode};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use serde::{Deserialize, Serialize};
+use tungstenite::{client::IntoClientRequest, stream::MaybeTlsStream, Connector, HandshakeError, WebSocket};
+
+// WiFi屏幕连接的加密和认证设置
+// 开启加密后使用 wss:// 和 https:// 连接，设置了令牌时所有请求和websocket握手都带上 Authorization: Bearer {令牌}
+// 令牌只通过加密连接发送，设置了令牌但没有开启加密时拒绝连接
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct WifiSecurity{
+    #[serde(default)]
+    pub tls: bool,
+    // 预共享令牌，和固件中设置的一致
+    #[serde(default)]
+    pub token: Option<String>,
+    // 屏幕证书的SHA-256指纹(十六进制)，固件使用自签名证书时设置，只信任这个证书
+    // 为空时使用系统根证书验证
+    #[serde(default)]
+    pub cert_sha256: Option<String>,
+}
+
+// 屏幕拒绝了令牌或者证书验证失败，重试也不会成功
+#[derive(Debug)]
+pub struct AuthError(pub String);
+
+impl fmt::Display for AuthError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for AuthError{}
+
+impl WifiSecurity{
+    // 解析证书指纹，支持 openssl 输出的冒号分隔格式，传入空字符串时清除
+    pub fn parse_fingerprint(s: &str) -> Result<String, String>{
+        let hex: String = s.trim().chars().filter(|c| *c != ':').collect::<String>().to_lowercase();
+        if !hex.is_empty() && (hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit())){
+            return Err(format!("证书指纹为64位十六进制的SHA-256: {s}"));
+        }
+        Ok(hex)
+    }
+
+    pub fn http_url(&self, ip: &str, path: &str) -> String{
+        format!("{}://{ip}{path}", if self.tls { "https" } else { "http" })
+    }
+
+    pub fn ws_url(&self, ip: &str) -> String{
+        format!("{}://{ip}/ws", if self.tls { "wss" } else { "ws" })
+    }
+
+    // 设置了证书指纹时使用只信任该证书的TLS配置
+    fn pinned_config(&self) -> Result<Option<ClientConfig>>{
+        let fingerprint = match self.cert_sha256.as_deref().map(str::trim).filter(|s| !s.is_empty()){
+            Some(fingerprint) => fingerprint,
+            None => return Ok(None),
+        };
+        let fingerprint = Self::parse_fingerprint(fingerprint).map_err(|err| anyhow!(err))?;
+        let fingerprint = (0..fingerprint.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&fingerprint[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+        let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+        let config = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier{ fingerprint, provider }))
+            .with_no_client_auth();
+        Ok(Some(config))
+    }
+
+    // 没有开启加密时令牌会在局域网中明文传输
+    fn check_token(&self) -> Result<()>{
+        if self.token.is_some() && !self.tls{
+            return Err(AuthError("设置了令牌但没有开启加密(--wifi-tls)，拒绝明文发送令牌".to_string()).into());
+        }
+        Ok(())
+    }
+
+    pub fn http_client(&self, timeout: Option<Duration>) -> Result<Client>{
+        self.check_token()?;
+        let mut headers = HeaderMap::new();
+        if let Some(token) = self.token.as_ref(){
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        let mut builder = Client::builder().default_headers(headers);
+        if let Some(timeout) = timeout{
+            builder = builder.timeout(timeout);
+        }
+        if let Some(config) = self.pinned_config()?{
+            builder = builder.tls_backend_preconfigured(config);
+        }
+        Ok(builder.build()?)
+    }
+
+    // 屏幕拒绝令牌或者证书验证失败时返回 AuthError
+    pub fn check_response(resp: reqwest::Result<Response>) -> Result<Response>{
+        let resp = match resp{
+            Ok(resp) => resp,
+            Err(err) => return Err(cert_error(&err).map(anyhow::Error::from).unwrap_or(err.into())),
+        };
+        match resp.status(){
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(AuthError(format!("屏幕拒绝了令牌(HTTP {})", resp.status())).into()),
+            _ => Ok(resp.error_for_status()?),
+        }
+    }
+
+    pub fn connect_websocket(&self, ip: &str) -> Result<WebSocket<MaybeTlsStream<TcpStream>>>{
+        self.check_token()?;
+        let mut request = self.ws_url(ip).into_client_request()?;
+        if let Some(token) = self.token.as_ref(){
+            request.headers_mut().insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {token}"))?);
+        }
+        let host = request.uri().host().ok_or(anyhow!("无效的地址:{ip}"))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+        let port = request.uri().port_u16().unwrap_or(if self.tls { 443 } else { 80 });
+        let stream = TcpStream::connect((host.as_str(), port))?;
+        stream.set_nodelay(true)?;
+        // TLS握手和等待ACK的超时时间
+        stream.set_read_timeout(Some(Duration::from_secs(3)))?;
+        let connector = self.pinned_config()?.map(|config| Connector::Rustls(Arc::new(config)));
+        match tungstenite::client_tls_with_config(request, stream, None, connector){
+            Ok((socket, _resp)) => Ok(socket),
+            Err(HandshakeError::Failure(err)) => Err(handshake_error(err)),
+            Err(HandshakeError::Interrupted(_)) => Err(anyhow!("websocket握手超时")),
+        }
+    }
+}
+
+fn handshake_error(err: tungstenite::Error) -> anyhow::Error{
+    match &err{
+        tungstenite::Error::Http(resp) if matches!(resp.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+            AuthError(format!("屏幕拒绝了令牌(HTTP {})", resp.status())).into()
+        }
+        _ => cert_error(&err).map(anyhow::Error::from).unwrap_or(err.into()),
+    }
+}
+
+// 在错误链中查找rustls的证书验证错误(io::Error 的 source() 会跳过内部错误，需要通过 get_ref() 展开)
+fn cert_error(err: &(dyn std::error::Error + 'static)) -> Option<AuthError>{
+    let mut next = Some(err);
+    while let Some(err) = next{
+        if let Some(rustls::Error::InvalidCertificate(cert_err)) = err.downcast_ref::<rustls::Error>(){
+            return Some(AuthError(format!("证书验证失败:{cert_err:?}")));
+        }
+        next = match err.downcast_ref::<std::io::Error>().and_then(|e| e.get_ref()){
+            Some(inner) => Some(inner as &(dyn std::error::Error + 'static)),
+            None => err.source(),
+        };
+    }
+    None
+}
+
+// 只比较证书的SHA-256指纹，不检查域名和有效期(屏幕通过IP访问，使用自签名证书)
+#[derive(Debug)]
+struct PinnedCertVerifier{
+    fingerprint: Vec<u8>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier{
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error>{
+        if digest(&SHA256, end_entity.as_ref()).as_ref() == self.fingerprint.as_slice(){
+            Ok(ServerCertVerified::assertion())
+        }else{
+            Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error>{
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error>{
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme>{
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[test]
+fn test_parse_fingerprint(){
+    let hex = "AB:CD:".repeat(16);
+    let hex = hex.trim_end_matches(':');
+    assert_eq!(WifiSecurity::parse_fingerprint(hex), Ok("abcd".repeat(16)));
+    assert_eq!(WifiSecurity::parse_fingerprint(""), Ok(String::new()));
+    assert!(WifiSecurity::parse_fingerprint("abcd").is_err());
+    assert!(WifiSecurity::parse_fingerprint(&"zz".repeat(32)).is_err());
+}
+
+#[test]
+fn test_token_requires_tls(){
+    let mut security = WifiSecurity{ token: Some("secret".to_string()), ..Default::default() };
+    assert!(security.http_client(None).unwrap_err().is::<AuthError>());
+    assert!(security.connect_websocket("127.0.0.1:1").unwrap_err().is::<AuthError>());
+    security.tls = true;
+    assert!(security.http_client(None).is_ok());
+}